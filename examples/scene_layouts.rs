@@ -0,0 +1,156 @@
+//!
+//! Writes the random layouts of `scene::random_spheres` and `scene::book2_final`, as they are
+//! drawn with the default seed, into `scenes/random_spheres.toml` and `scenes/book2_final.toml`.
+//! Everything below the marker line in each file is replaced. Run it after changing how random
+//! numbers are drawn, which `cargo test` catches as the files no longer matching:
+//!
+//! ```sh
+//! cargo run --example scene_layouts
+//! ```
+//!
+//! The draws mirror the ones in `scene.rs`, and have to be changed along with them.
+//!
+
+use std::{fmt::Write, fs, path::Path};
+
+use rand::{
+    distributions::{Distribution, Uniform},
+    Rng,
+};
+use raytracer_v2::{rng, Color, Point3};
+
+const MARKER: &str = "# Written out by `cargo run --example scene_layouts`";
+
+fn main() {
+    rng::set_seed(0);
+    let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+    for (name, layout) in [
+        ("random_spheres", random_spheres()),
+        ("book2_final", book2_final()),
+    ] {
+        let path = scenes.join(format!("{name}.toml"));
+        let source = fs::read_to_string(&path).unwrap();
+        let Some(end) = source.find(MARKER) else {
+            panic!("{} has no line starting with {MARKER}", path.display());
+        };
+        let end = end
+            + source[end..]
+                .find('\n')
+                .map_or(source.len() - end, |n| n + 1);
+        fs::write(&path, format!("{}{layout}", &source[..end])).unwrap();
+        println!("Wrote {}", path.display());
+    }
+}
+
+fn vec3(v: Point3) -> String {
+    format!("[{:?}, {:?}, {:?}]", v.x(), v.y(), v.z())
+}
+
+fn color(c: Color) -> String {
+    format!("[{:?}, {:?}, {:?}]", c.r(), c.g(), c.b())
+}
+
+///
+/// The small spheres and their materials
+///
+fn random_spheres() -> String {
+    rng::start_scene_stream();
+
+    const BALL_RADIUS: f64 = 0.2;
+
+    let mut rng = rng::thread_rng();
+    let ball_dist_center = Point3::new(4.0, BALL_RADIUS, 0.0);
+    let metal_color_dist = Uniform::from(0.5..1.0);
+    let metal_fuzz_dist = Uniform::from(0.0..0.5);
+    let diffuse_color_dist = Uniform::from(0.0..1.0);
+
+    let mut out = String::new();
+    let mut balls = 0;
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat: f64 = rng.gen();
+            let center = Point3::new(
+                a as f64 + 0.9 * rng.gen::<f64>(),
+                BALL_RADIUS,
+                b as f64 + 0.9 * rng.gen::<f64>(),
+            );
+
+            if (center - ball_dist_center).length() <= 0.9 {
+                continue;
+            }
+            let material = match choose_mat {
+                ..=0.8 => {
+                    let albedo = Color::random_range(diffuse_color_dist, &mut rng)
+                        * Color::random_range(diffuse_color_dist, &mut rng);
+                    let name = format!("ball_{balls}");
+                    balls += 1;
+                    writeln!(out, "\n[materials.{name}]").unwrap();
+                    writeln!(out, "type = \"lambertian\"").unwrap();
+                    writeln!(out, "albedo = {}", color(albedo)).unwrap();
+                    name
+                }
+                ..=0.95 => {
+                    let albedo = Color::random_range(metal_color_dist, &mut rng);
+                    let fuzz = rng.sample(metal_fuzz_dist);
+                    let name = format!("ball_{balls}");
+                    balls += 1;
+                    writeln!(out, "\n[materials.{name}]").unwrap();
+                    writeln!(out, "type = \"metal\"").unwrap();
+                    writeln!(out, "albedo = {}", color(albedo)).unwrap();
+                    writeln!(out, "fuzz = {fuzz:?}").unwrap();
+                    name
+                }
+                _ => "glass".to_string(),
+            };
+
+            writeln!(out, "\n[[objects]]").unwrap();
+            writeln!(out, "type = \"sphere\"").unwrap();
+            writeln!(out, "center = {}", vec3(center)).unwrap();
+            writeln!(out, "radius = {BALL_RADIUS:?}").unwrap();
+            writeln!(out, "material = \"{material}\"").unwrap();
+            writeln!(out, "group = \"world\"").unwrap();
+        }
+    }
+    out
+}
+
+///
+/// The heights of the ground boxes and the positions of the small spheres
+///
+fn book2_final() -> String {
+    rng::start_scene_stream();
+
+    let mut out = String::new();
+
+    const BOXES_PER_SIDE: usize = 20;
+    let height_dist = Uniform::from(1.0..=101.0);
+    let mut rng = rng::thread_rng();
+    for i in 0..BOXES_PER_SIDE {
+        for j in 0..BOXES_PER_SIDE {
+            const W: f64 = 100.0;
+            let x0 = -1000.0 + i as f64 * W;
+            let z0 = -1000.0 + j as f64 * W;
+            let y1 = height_dist.sample(&mut rng);
+
+            writeln!(out, "\n[[objects]]").unwrap();
+            writeln!(out, "type = \"box\"").unwrap();
+            writeln!(out, "a = {}", vec3(Point3::new(x0, 0.0, z0))).unwrap();
+            writeln!(out, "b = {}", vec3(Point3::new(x0 + W, y1, z0 + W))).unwrap();
+            writeln!(out, "material = \"ground\"").unwrap();
+            writeln!(out, "group = \"ground\"").unwrap();
+        }
+    }
+
+    const SPHERE_COUNT: usize = 1000;
+    let sphere_dist = Uniform::from(0.0..165.0);
+    for _ in 0..SPHERE_COUNT {
+        let center = Point3::random_dist(&sphere_dist, &mut rng);
+        writeln!(out, "\n[[objects]]").unwrap();
+        writeln!(out, "type = \"sphere\"").unwrap();
+        writeln!(out, "center = {}", vec3(center)).unwrap();
+        writeln!(out, "radius = 10.0").unwrap();
+        writeln!(out, "material = \"white\"").unwrap();
+        writeln!(out, "group = \"spheres\"").unwrap();
+    }
+    out
+}
@@ -0,0 +1,60 @@
+//!
+//! Rendering image sequences. An animation divides a span of ray time into frames, and exposes
+//! each frame for part of its share of that time, like the rotating shutter of a film camera.
+//! Objects and cameras move with keyframes over the ray time, so the same keyframes give both
+//! the motion across frames and the motion blur within each one.
+//!
+
+use anyhow::{bail, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Animation {
+    pub frames: u32,        // Number of frames
+    pub start_time: f64,    // Ray time at which the first frame starts
+    pub end_time: f64,      // Ray time at which the last frame ends
+    pub shutter_angle: f64, // Share of each frame the shutter is open, in degrees out of 360
+}
+
+impl Animation {
+    ///
+    /// `frames` frames over ray times 0 to 1, the span over which moving spheres move, with a
+    /// 180 degree shutter
+    ///
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames,
+            start_time: 0.0,
+            end_time: 1.0,
+            shutter_angle: 180.0,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.frames == 0 {
+            bail!("an animation needs at least one frame");
+        }
+        if self.end_time < self.start_time {
+            bail!("the animation ends before it starts");
+        }
+        if !(0.0..=360.0).contains(&self.shutter_angle) {
+            bail!("the shutter angle must be between 0 and 360 degrees");
+        }
+        Ok(())
+    }
+
+    ///
+    /// Ray times at which the shutter opens and closes for `frame`, counting from 1
+    ///
+    pub fn shutter(&self, frame: u32) -> (f64, f64) {
+        let frame_time = (self.end_time - self.start_time) / self.frames as f64;
+        let open = self.start_time + (frame - 1) as f64 * frame_time;
+        (open, open + frame_time * self.shutter_angle / 360.0)
+    }
+
+    ///
+    /// Name of the image of `frame`, such as frame_0001.png
+    ///
+    pub fn file_name(frame: u32, extension: &str) -> String {
+        format!("frame_{frame:04}.{extension}")
+    }
+}
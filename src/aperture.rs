@@ -0,0 +1,162 @@
+//!
+//! Shapes of the camera's aperture, which out of focus highlights (bokeh) take on. Points on the
+//! aperture are drawn around the center with a radius of up to 1, which the camera scales to the
+//! size of its defocus disk.
+//!
+
+use std::f64::consts::PI;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    rng::random,
+    texture::Texture,
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Clone, Default)]
+pub enum Aperture {
+    /// A round opening
+    #[default]
+    Disk,
+
+    ///
+    /// A regular polygon formed by `blades` straight blades, with its corners on the unit circle,
+    /// turned `rotation` degrees counterclockwise
+    ///
+    Polygon { blades: u32, rotation: f64 },
+
+    ///
+    /// An opening shaped by the brightness of a texture, such as a grayscale image, stretched
+    /// over the square around the unit circle. Brighter parts let more light through.
+    ///
+    Texture(Texture),
+}
+
+// Cells per side of the grid a texture aperture is sampled with
+const GRID_SIZE: usize = 128;
+
+impl Aperture {
+    ///
+    /// Fails if a polygon has fewer than 3 blades, or a texture is black all over, so that no
+    /// light gets through
+    ///
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Aperture::Disk => {}
+            Aperture::Polygon { blades, .. } => {
+                if *blades < 3 {
+                    bail!("an aperture needs at least 3 blades");
+                }
+            }
+            Aperture::Texture(texture) => {
+                if *grid_cdf(texture).last().unwrap() <= 0.0 {
+                    bail!("an aperture texture needs a bright part to let light through");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// Draws points on an aperture
+///
+pub(crate) enum ApertureSampler {
+    Disk,
+    Polygon {
+        blades: u32,
+        rotation: f64, // In radians
+    },
+    Grid {
+        cdf: Vec<f64>, // Running total of the brightness of the cells, by rows from the bottom
+    },
+}
+
+impl ApertureSampler {
+    ///
+    /// Panics if the aperture is not valid, see `Aperture::validate`
+    ///
+    pub(crate) fn new(aperture: &Aperture) -> Self {
+        match aperture {
+            Aperture::Disk => ApertureSampler::Disk,
+
+            Aperture::Polygon { blades, rotation } => {
+                assert!(*blades >= 3, "An aperture needs at least 3 blades");
+                ApertureSampler::Polygon {
+                    blades: *blades,
+                    rotation: rotation.to_radians(),
+                }
+            }
+
+            Aperture::Texture(texture) => {
+                let cdf = grid_cdf(texture);
+                assert!(
+                    *cdf.last().unwrap() > 0.0,
+                    "An aperture texture needs a bright part to let light through"
+                );
+                ApertureSampler::Grid { cdf }
+            }
+        }
+    }
+
+    pub(crate) fn sample(&self) -> Vec3 {
+        match self {
+            ApertureSampler::Disk => Vec3::random_in_unit_disk(),
+
+            ApertureSampler::Polygon { blades, rotation } => {
+                // Every blade's triangle from the center is the same size, pick one and then a
+                // uniform point in it
+                let blade = (random::<f64>() * *blades as f64) as u32 % blades;
+                let step = 2.0 * PI / *blades as f64;
+                let (a, b) = (
+                    rotation + blade as f64 * step,
+                    rotation + (blade + 1) as f64 * step,
+                );
+                let (mut s, mut t) = (random::<f64>(), random::<f64>());
+                if s + t > 1.0 {
+                    (s, t) = (1.0 - s, 1.0 - t);
+                }
+                Vec3::new(s * a.cos() + t * b.cos(), s * a.sin() + t * b.sin(), 0.0)
+            }
+
+            ApertureSampler::Grid { cdf } => {
+                let target = random::<f64>() * cdf[cdf.len() - 1];
+                let cell = cdf.partition_point(|c| *c <= target).min(cdf.len() - 1);
+                let (x, y) = cell_corner(cell);
+                let cell_width = 2.0 / GRID_SIZE as f64;
+                Vec3::new(
+                    x + random::<f64>() * cell_width,
+                    y + random::<f64>() * cell_width,
+                    0.0,
+                )
+            }
+        }
+    }
+}
+
+///
+/// Running total of the brightness of the cells of the sampling grid, by rows from the bottom
+///
+fn grid_cdf(texture: &Texture) -> Vec<f64> {
+    (0..GRID_SIZE * GRID_SIZE)
+        .scan(0.0, |total, cell| {
+            // Brightness at the center of the cell
+            let (x, y) = cell_corner(cell);
+            let (x, y) = (x + 1.0 / GRID_SIZE as f64, y + 1.0 / GRID_SIZE as f64);
+            let (u, v) = (0.5 * (x + 1.0), 0.5 * (y + 1.0));
+            let value = texture.value(u, v, Point3::new(x, y, 0.0));
+            *total += value.luminance().max(0.0);
+            Some(*total)
+        })
+        .collect()
+}
+
+///
+/// Lower left corner of a cell of the sampling grid, which covers -1 to 1 on both axes
+///
+fn cell_corner(cell: usize) -> (f64, f64) {
+    let (i, j) = (cell % GRID_SIZE, cell / GRID_SIZE);
+    let cell_width = 2.0 / GRID_SIZE as f64;
+    (-1.0 + i as f64 * cell_width, -1.0 + j as f64 * cell_width)
+}
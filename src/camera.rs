@@ -1,32 +1,323 @@
-use std::{env, f64::INFINITY, io::Write};
+use std::{f64::consts::PI, str::FromStr};
 
-use anyhow::Result;
-use image::RgbImage;
-use rand::{
-    distributions::{Distribution, Uniform},
-    random,
-};
+use anyhow::{anyhow, bail, Result};
+use rand::distributions::{Distribution, Uniform};
 use rayon::prelude::*;
+use serde::{de::IntoDeserializer, Deserialize};
 
 use crate::{
-    color::{write_row, Color},
-    hittable::Hittable,
+    aperture::{Aperture, ApertureSampler},
+    color::Color,
+    filter::Filter,
+    framebuffer::{Framebuffer, WeightedFramebuffer},
+    hittable::{hittable_list::HittableList, HitRecord, Hittable},
+    material::Scatter,
+    pdf::Pdf,
     ray::Ray,
+    rng::{self, random},
+    spectrum::Wavelengths,
     vec3::{Point3, Vec3},
 };
 
+#[derive(Clone)]
 pub struct Camera {
-    pub aspect_ratio: f64,         // Ratio of image width over height
-    pub image_width: u32,          // Rendered image width in pixel count
-    pub samples_per_pixel: u32,    // Number of samples for each pixel
-    pub max_depth: u32,            // Maximum number of ray bounces into scene
-    pub vfov: f64,                 // Vertical view angle (field of view) in degrees
-    pub look_from: Point3,         // Point camera is looking from
-    pub look_at: Point3,           // Point camera is looking at
-    pub v_up: Vec3,                // Camera-relative "up" direction
-    pub defocus_angle: f64,        // Variation angle of rays through each pixel
-    pub focus_dist: f64,           // Distance from camera look_from point to plane of perfect focus
+    pub aspect_ratio: f64,             // Ratio of image width over height
+    pub image_width: u32,              // Rendered image width in pixel count
+    pub samples_per_pixel: u32,        // Number of samples for each pixel
+    pub max_depth: u32,                // Maximum number of ray bounces into scene
+    pub vfov: f64,                     // Vertical view angle (field of view) in degrees
+    pub look_from: Point3,             // Point camera is looking from
+    pub look_at: Point3,               // Point camera is looking at
+    pub v_up: Vec3,                    // Camera-relative "up" direction
+    pub defocus_angle: f64,            // Variation angle of rays through each pixel
+    pub focus_dist: f64, // Distance from camera look_from point to plane of perfect focus
     pub background: Option<Color>, // Color to ues as the background of the image, when nothing is hit. If None, use gradient
+    pub sampling: Sampling,        // How rays scattered off non-specular materials are chosen
+    pub heuristic: MisHeuristic,   // How light and material samples are weighed by Sampling::Mis
+    pub shutter_open: f64,         // Ray time at which the shutter opens
+    pub shutter_close: f64,        // Ray time at which the shutter closes
+    pub shutter_curve: ShutterCurve, // How the exposure is spread over the time the shutter is open
+    pub motion: Vec<CameraKeyframe>, // Moves the camera while the shutter is open, replacing its placement and lens settings unless empty
+    pub projection: Projection,      // How directions from the camera map onto the image
+    pub view_width: f64, // Width of the view of Projection::Orthographic, in world units
+    pub fisheye_fov: f64, // Field of view of the fisheye projections across the image width, in degrees
+    pub eye: Eye,         // Which eye of a stereo pair to render, or Center for a single image
+    pub ipd: f64,         // Interpupillary distance, between the eyes of a stereo pair
+    pub convergence_dist: Option<f64>, // Distance at which the eyes' views meet, the focus distance if None
+    pub stereo_mode: StereoMode,       // How the eyes of a stereo pair are aimed
+    pub aperture: Aperture, // Shape of the defocus disk, which out of focus highlights take on
+    pub anamorphic_squeeze: f64, // How many times narrower the aperture is than it is tall, for the oval bokeh of anamorphic lenses
+    pub vignetting: f64, // How far the lens barrel cuts into the aperture towards the corners, as a fraction of its radius, for cat-eye bokeh. 0 for none
+    pub filter: Filter,  // How samples are weighed into the pixels around them
+    pub filter_radius: Option<f64>, // Reach of the filter in pixels, raised to 0.5 if less. If None, the filter's default
+    pub spectral: bool,             // Whether rays carry wavelengths instead of RGB, for dispersion
+}
+
+///
+/// Where the camera sits between the eyes of a stereo pair
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Eye {
+    /// Midway between the eyes, for ordinary images
+    #[default]
+    Center,
+    Left,
+    Right,
+}
+
+///
+/// How the eyes of a stereo pair are aimed. The equirectangular projection ignores this, and
+/// renders omnidirectional stereo instead: each ray starts from the point on a circle of
+/// diameter `ipd` where an eye looking along it would be, with the circle shrinking towards the
+/// poles to keep them comfortable to look at.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoMode {
+    ///
+    /// Both eyes look straight ahead, with their image planes shifted so that they line up at
+    /// the convergence distance. This avoids the vertical parallax of toe-in.
+    ///
+    #[default]
+    OffAxis,
+
+    /// Each eye turns inwards to look at the point at the convergence distance
+    ToeIn,
+}
+
+impl FromStr for StereoMode {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+///
+/// How the camera maps directions onto the image. The defocus disk blurs the perspective and
+/// orthographic projections only.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    /// A pinhole or thin lens camera, seeing `vfov` degrees vertically
+    #[default]
+    Perspective,
+
+    /// Parallel rays along the view direction, across `view_width` in world units
+    Orthographic,
+
+    ///
+    /// A circular fisheye seeing `fisheye_fov` degrees across the image width, where the distance
+    /// from the center of the image is proportional to the angle from the view direction.
+    /// Pixels outside the circle are black.
+    ///
+    FisheyeEquidistant,
+
+    /// Like `FisheyeEquidistant`, with distances that preserve the relative area of objects
+    FisheyeEquisolid,
+
+    ///
+    /// A 360 degree latitude-longitude panorama, with the view direction in the center and
+    /// `v_up` at the top. It is always twice as wide as it is tall, as environment maps are.
+    ///
+    Equirectangular,
+}
+
+impl FromStr for Projection {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+///
+/// How much light reaches the image over the time the shutter is open. Ray times are drawn in
+/// proportion to it.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ShutterCurve {
+    /// Fully open from opening to closing
+    #[default]
+    Box,
+
+    /// Opening steadily until halfway, then closing steadily
+    Triangle,
+
+    ///
+    /// Weights at evenly spaced times from opening to closing, linearly interpolated in between.
+    /// Made by `ShutterCurve::custom`, which checks them.
+    ///
+    Custom(ShutterWeights),
+}
+
+///
+/// The weights of a custom shutter curve, at least two, none negative and not all 0
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutterWeights(Vec<f64>);
+
+impl ShutterWeights {
+    pub fn as_slice(&self) -> &[f64] {
+        &self.0
+    }
+}
+
+impl ShutterCurve {
+    ///
+    /// Needs at least two weights, none negative and not all 0
+    ///
+    pub fn custom(weights: Vec<f64>) -> Result<Self> {
+        if weights.len() < 2 {
+            bail!("a shutter curve needs at least two weights");
+        }
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            bail!("shutter curve weights cannot be negative");
+        }
+        if weights.iter().all(|w| *w == 0.0) {
+            bail!("a shutter curve needs a weight greater than 0");
+        }
+        Ok(ShutterCurve::Custom(ShutterWeights(weights)))
+    }
+
+    fn weights(&self) -> Vec<f64> {
+        match self {
+            ShutterCurve::Box => vec![1.0, 1.0],
+            ShutterCurve::Triangle => vec![0.0, 1.0, 0.0],
+            ShutterCurve::Custom(weights) => weights.0.clone(),
+        }
+    }
+}
+
+impl FromStr for ShutterCurve {
+    type Err = anyhow::Error;
+
+    ///
+    /// Parses box, triangle, or comma separated weights for a custom curve
+    ///
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "box" => Ok(ShutterCurve::Box),
+            "triangle" => Ok(ShutterCurve::Triangle),
+            _ => {
+                let weights = s
+                    .split(',')
+                    .map(|w| w.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| {
+                        anyhow!("`{s}` is not a shutter curve, expected box, triangle or weights")
+                    })?;
+                ShutterCurve::custom(weights)
+            }
+        }
+    }
+}
+
+///
+/// Where the camera is, where it looks and how its lens is set at one point in time. Between
+/// keyframes, each is interpolated linearly. `Camera::keyframe` makes one from the camera's own
+/// settings.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub v_up: Vec3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+    pub defocus_angle: f64,
+}
+
+impl CameraKeyframe {
+    fn lerp(&self, other: &CameraKeyframe, t: f64) -> CameraKeyframe {
+        CameraKeyframe {
+            time: (1.0 - t) * self.time + t * other.time,
+            look_from: (1.0 - t) * self.look_from + t * other.look_from,
+            look_at: (1.0 - t) * self.look_at + t * other.look_at,
+            v_up: (1.0 - t) * self.v_up + t * other.v_up,
+            vfov: (1.0 - t) * self.vfov + t * other.vfov,
+            focus_dist: (1.0 - t) * self.focus_dist + t * other.focus_dist,
+            defocus_angle: (1.0 - t) * self.defocus_angle + t * other.defocus_angle,
+        }
+    }
+}
+
+///
+/// Strategies for choosing the rays scattered off non-specular materials. All of them converge to
+/// the same image, with different amounts of noise. When there are no lights to sample, they all
+/// behave like `Bsdf`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sampling {
+    /// Only follow the material's own distribution, finding lights by chance
+    Bsdf,
+
+    ///
+    /// Sample a light directly at every bounce, and follow the material for indirect light only.
+    /// Sampled lights hit by following the material are ignored, as they are already counted.
+    ///
+    Light,
+
+    /// Pick either a light or the material at random for every bounce
+    Mixture,
+
+    ///
+    /// Sample both a light and the material at every bounce, combining them with multiple
+    /// importance sampling
+    ///
+    #[default]
+    Mis,
+}
+
+///
+/// Weights for combining light and material samples, from Veach's thesis
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MisHeuristic {
+    /// Weigh each sample by its share of the summed densities
+    Balance,
+
+    /// Weigh each sample by its share of the summed squared densities
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    ///
+    /// Weight of a sample taken with density `pdf`, when `other_pdf` is the density of the other
+    /// strategy generating the same direction
+    ///
+    fn weight(self, pdf: f64, other_pdf: f64) -> f64 {
+        let (a, b) = match self {
+            MisHeuristic::Balance => (pdf, other_pdf),
+            MisHeuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+        if a + b > 0.0 {
+            a / (a + b)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl FromStr for Sampling {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+impl FromStr for MisHeuristic {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
 }
 
 impl Camera {
@@ -44,11 +335,62 @@ impl Camera {
             defocus_angle: 0.0,
             focus_dist: 10.0,
             background: None,
+            sampling: Sampling::default(),
+            heuristic: MisHeuristic::default(),
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            shutter_curve: ShutterCurve::default(),
+            motion: Vec::new(),
+            projection: Projection::default(),
+            view_width: 10.0,
+            fisheye_fov: 180.0,
+            eye: Eye::default(),
+            ipd: 0.064,
+            convergence_dist: None,
+            stereo_mode: StereoMode::default(),
+            aperture: Aperture::default(),
+            anamorphic_squeeze: 1.0,
+            vignetting: 0.0,
+            filter: Filter::default(),
+            filter_radius: None,
+            spectral: false,
         }
     }
 
-    pub fn render(&self, world: &Hittable) -> Result<()> {
-        CameraCore::initialize(self).render(world)
+    ///
+    /// A keyframe at `time` that places the camera as its own fields do
+    ///
+    pub fn keyframe(&self, time: f64) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            look_from: self.look_from,
+            look_at: self.look_at,
+            v_up: self.v_up,
+            vfov: self.vfov,
+            focus_dist: self.focus_dist,
+            defocus_angle: self.defocus_angle,
+        }
+    }
+
+    ///
+    /// Renders `world` into an in-memory image. `lights` should hold the emissive objects of the
+    /// world that are worth sampling directly, as chosen by `sampling`. It can be empty.
+    ///
+    pub fn render(&self, world: &Hittable, lights: &HittableList) -> Framebuffer {
+        self.render_with_progress(world, lights, |_, _| {})
+    }
+
+    ///
+    /// Renders like `render`, calling `progress` with the number of finished rows and the image
+    /// height after each row
+    ///
+    pub fn render_with_progress(
+        &self,
+        world: &Hittable,
+        lights: &HittableList,
+        progress: impl FnMut(u32, u32),
+    ) -> Framebuffer {
+        CameraCore::initialize(self).render(world, lights, progress)
     }
 }
 
@@ -59,109 +401,350 @@ struct CameraCore {
 
     image_height: u32,        // Rendered image height
     pixel_samples_scale: f64, // Color scale factor for a sum of pixel samples
-    center: Point3,           // Camera center
-    pixel_00_loc: Point3,     // Location of pixel 0, 0
-    pixel_delta_u: Point3,    // Offset to pixel to the right
-    pixel_delta_v: Point3,    // Offset to pixel below
+    filter: Filter,           // How samples are weighed into the pixels around them
+    filter_radius: f64,       // Reach of the filter in pixels
+    spectral: bool,           // Whether rays carry wavelengths instead of RGB
+
+    view: View,                  // Camera placement when it doesn't move
+    motion: Vec<CameraKeyframe>, // Keyframes sorted by time, or empty if the camera doesn't move
+    shutter: Shutter,            // Draws ray times
+
+    defocus: bool, // Whether rays start anywhere on the defocus disk, at any time
+    aperture: ApertureSampler, // Draws points on the defocus disk
+    anamorphic_squeeze: f64, // Divides the width of the defocus disk
+    vignetting: f64, // How far the aperture is cut off towards the corners
+
+    optics: Optics,   // Shapes the view however the camera moves
+    fisheye_fov: f64, // Field of view of the fisheye projections across the image width
+
+    background: Option<Color>, // Color to ues as the background of the image, when nothing is hit. If None, use gradient
+
+    sampling: Sampling, // How rays scattered off non-specular materials are chosen
+    heuristic: MisHeuristic, // How light and material samples are weighed by Sampling::Mis
+}
+
+///
+/// The settings that shape the camera's view, however it moves
+///
+struct Optics {
+    image_width: u32,
+    image_height: u32,
+    projection: Projection, // How directions from the camera map onto the image
+    view_width: f64,        // Width of the view of Projection::Orthographic
+    eye_offset: f64, // Distance of the eye to the right of the camera, negative for the left eye
+    convergence_dist: Option<f64>, // Distance at which the eyes' views meet, the focus distance if None
+    stereo_mode: StereoMode,       // How the eyes of a stereo pair are aimed
+}
+
+///
+/// The camera's placement in the world, from which rays are cast
+///
+struct View {
+    center: Point3,        // Camera center
+    pixel_00_loc: Point3,  // Location of pixel 0, 0
+    pixel_delta_u: Point3, // Offset to pixel to the right
+    pixel_delta_v: Point3, // Offset to pixel below
 
-    #[allow(dead_code)]
     u: Vec3, // Camera Frame Basis Vector: Left relative to camera
-    #[allow(dead_code)]
     v: Vec3, // Camera Frame Basis Vector: Up relative to camera
-    #[allow(dead_code)]
     w: Vec3, // Camera Frame Basis Vector: Behind relative to camera (we look along the -w axis)
 
-    defocus_angle: f64,   // Variation angle of rays through each pixel
+    focus_dist: f64, // Distance from camera look_from point to plane of perfect focus
+
     defocus_disk_u: Vec3, // Defocus disk horizontal radius
     defocus_disk_v: Vec3, // Defocus disk vertical radius
+}
 
-    background: Option<Color>, // Color to ues as the background of the image, when nothing is hit. If None, use gradient
+impl View {
+    ///
+    /// The viewport is used by the perspective and orthographic projections. For the orthographic
+    /// one it is `view_width` wide, and goes through the center of the camera rather than the
+    /// plane of perfect focus.
+    ///
+    fn new(optics: &Optics, key: &CameraKeyframe) -> Self {
+        let Optics {
+            image_width,
+            image_height,
+            projection,
+            view_width,
+            eye_offset,
+            ..
+        } = *optics;
+
+        // Viewport Dimensions
+        let (viewport_height, viewport_distance) = match projection {
+            Projection::Orthographic => {
+                (view_width * image_height as f64 / image_width as f64, 0.0)
+            }
+            _ => {
+                let theta = key.vfov.to_radians();
+                let h = (theta / 2.0).tan();
+                (2.0 * h * key.focus_dist, key.focus_dist)
+            }
+        };
+        let viewport_width = viewport_height * (image_width as f64 / image_height as f64);
+
+        // Calculate the u, v, w unit basis vectors for the camera coordinate frame.
+        let w = (key.look_from - key.look_at).unit_vector();
+        let u = (key.v_up.cross(w)).unit_vector();
+        let v = w.cross(u);
+
+        // Move to the eye. Omnidirectional stereo moves each ray instead.
+        let convergence_dist = optics.convergence_dist.unwrap_or(key.focus_dist);
+        let mut center = key.look_from;
+        let mut eye_shift = Vec3::new(0.0, 0.0, 0.0);
+        let (u, v, w) = match (projection, optics.stereo_mode) {
+            (Projection::Equirectangular, _) => (u, v, w),
+            (_, StereoMode::OffAxis) => {
+                center += eye_offset * u;
+                // Slide the viewport back towards the middle, so that both eyes' viewports
+                // cover the same window at the convergence distance
+                eye_shift = -eye_offset * (viewport_distance / convergence_dist) * u;
+                (u, v, w)
+            }
+            (_, StereoMode::ToeIn) => {
+                center += eye_offset * u;
+                let target = key.look_from - convergence_dist * w;
+                let w = (center - target).unit_vector();
+                let u = (key.v_up.cross(w)).unit_vector();
+                (u, w.cross(u), w)
+            }
+        };
+
+        // Panoramas stay level, so that they line up with environment maps
+        let (v, w) = match projection {
+            Projection::Equirectangular => {
+                let v = key.v_up.unit_vector();
+                (v, u.cross(v))
+            }
+            _ => (v, w),
+        };
+
+        // Viewport Vectors
+        let viewport_u = viewport_width * u; // Horizontal across the viewport, starting at the left
+        let viewport_v = viewport_height * -v; // Vertical across the viewport, starting at the top
+
+        // Viewport pixel delta vectors
+        let pixel_delta_u = viewport_u / image_width as f64;
+        let pixel_delta_v = viewport_v / image_height as f64;
+
+        // Find upper left pixel
+        let viewport_upper_left = // upper left corner of the viewport
+        center - (viewport_distance * w) - viewport_u / 2.0 - viewport_v / 2.0 + eye_shift;
+        let pixel_00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v); // First pixel is half a pixel delta from the top left corner
+
+        // Calculate the camera defocus disk basis vectors
+        let defocus_radius = key.focus_dist * (key.defocus_angle / 2.0).to_radians().tan();
+        let defocus_disk_u = u * defocus_radius;
+        let defocus_disk_v = v * defocus_radius;
+
+        View {
+            center,
+            pixel_00_loc,
+            pixel_delta_u,
+            pixel_delta_v,
+
+            u,
+            v,
+            w,
+
+            focus_dist: key.focus_dist,
+
+            defocus_disk_u,
+            defocus_disk_v,
+        }
+    }
+}
+
+///
+/// Draws ray times between the shutter opening and closing, following a shutter curve
+///
+struct Shutter {
+    open: f64,         // Time the shutter opens
+    length: f64,       // Time the shutter stays open
+    weights: Vec<f64>, // Curve weights at evenly spaced times
+    cdf: Vec<f64>,     // Area under the curve up to the end of each segment, ending at 1
+}
+
+impl Shutter {
+    fn new(open: f64, close: f64, curve: &ShutterCurve) -> Self {
+        let weights = curve.weights();
+        let mut cdf: Vec<f64> = weights
+            .windows(2)
+            .scan(0.0, |area, w| {
+                *area += 0.5 * (w[0] + w[1]);
+                Some(*area)
+            })
+            .collect();
+        // `ShutterCurve::custom` makes sure that there is some area to normalize by
+        let total = cdf[cdf.len() - 1];
+        cdf.iter_mut().for_each(|c| *c /= total);
+
+        Self {
+            open,
+            length: close - open,
+            weights,
+            cdf,
+        }
+    }
+
+    fn sample(&self) -> f64 {
+        let u = random::<f64>();
+
+        // Find the segment, then invert the area under its linear ramp
+        let segment = self
+            .cdf
+            .partition_point(|c| *c <= u)
+            .min(self.cdf.len() - 1);
+        let start = if segment == 0 {
+            0.0
+        } else {
+            self.cdf[segment - 1]
+        };
+        let (w0, w1) = (self.weights[segment], self.weights[segment + 1]);
+        let segment_area = 0.5 * (w0 + w1);
+        let area = (u - start) / (self.cdf[segment] - start) * segment_area;
+        let slope = w1 - w0;
+        let s = if slope.abs() < 1e-9 {
+            area / w0
+        } else {
+            // Solve w0 s + slope s^2 / 2 = area
+            let root = (w0 * w0 + 2.0 * slope * area).max(0.0).sqrt();
+            (root - w0) / slope
+        };
+
+        let t = (segment as f64 + s.clamp(0.0, 1.0)) / self.cdf.len() as f64;
+        self.open + t * self.length
+    }
 }
 
 impl CameraCore {
-    fn render(&self, world: &Hittable) -> Result<()> {
-        let mut buf = RgbImage::new(self.image_width, self.image_height);
+    fn render(
+        &self,
+        world: &Hittable,
+        lights: &HittableList,
+        mut progress: impl FnMut(u32, u32),
+    ) -> Framebuffer {
+        let mut buf = Framebuffer::new(self.image_width, self.image_height);
+
+        // A box filter of half a pixel averages each pixel's own samples, other filters spread
+        // the samples over the pixels around them
+        let mut splats = (self.filter != Filter::Box || self.filter_radius != 0.5)
+            .then(|| WeightedFramebuffer::new(self.image_width, self.image_height));
 
-        let mut stdout = std::io::stdout().lock();
         for j in 0..self.image_height {
-            write!(
-                stdout,
-                "\rScanlines remaining: {}                         ",
-                self.image_height - j
-            )?;
-            stdout.flush()?;
-            let row: Vec<Color> = (0..self.image_width)
+            // Each pixel's samples, with their offsets from the pixel center
+            let row: Vec<Vec<(Vec3, Color)>> = (0..self.image_width)
                 .into_par_iter()
                 .map(|i| {
                     (0..self.samples_per_pixel)
-                        .into_iter()
-                        .map(|_| {
-                            let r = self.get_ray(i, j);
-                            self.ray_color(r, self.max_depth, world)
+                        .map(|sample| {
+                            // Each sample draws from its own stream, so the image doesn't
+                            // depend on which thread renders which pixel
+                            rng::start_stream(&[j as u64, i as u64, sample as u64]);
+                            let offset = sample_square();
+                            let color = match self.get_ray(i, j, offset) {
+                                Some(r) if self.spectral => {
+                                    let wavelengths = Wavelengths::sample();
+                                    let r = r.with_wavelengths(Some(wavelengths));
+                                    wavelengths.to_rgb(self.ray_color(
+                                        r,
+                                        self.max_depth,
+                                        world,
+                                        lights,
+                                        None,
+                                    ))
+                                }
+                                Some(r) => self.ray_color(r, self.max_depth, world, lights, None),
+                                None => Color::black(),
+                            };
+                            (offset, color)
                         })
-                        .sum::<Color>()
-                        * self.pixel_samples_scale
+                        .collect()
                 })
                 .collect();
 
-            write_row(&mut buf, &row, j)
+            match &mut splats {
+                Some(splats) => {
+                    for (i, samples) in row.iter().enumerate() {
+                        for (offset, color) in samples {
+                            splats.splat(
+                                i as f64 + 0.5 + offset.x(),
+                                j as f64 + 0.5 + offset.y(),
+                                *color,
+                                self.filter,
+                                self.filter_radius,
+                            );
+                        }
+                    }
+                }
+                None => {
+                    let row: Vec<Color> = row
+                        .iter()
+                        .map(|samples| {
+                            samples.iter().map(|(_, color)| *color).sum::<Color>()
+                                * self.pixel_samples_scale
+                        })
+                        .collect();
+                    buf.set_row(j, &row);
+                }
+            }
+            progress(j + 1, self.image_height);
         }
 
-        buf.save_with_format(
-            &env::args().nth(1).unwrap_or("output/test.png".to_string()),
-            image::ImageFormat::Png,
-        )?;
-        write!(
-            stdout,
-            "\rDone.                                                 \n"
-        )?;
-
-        Ok(())
+        match splats {
+            Some(splats) => splats.resolve(),
+            None => buf,
+        }
     }
 
     fn initialize(params: &Camera) -> Self {
         let image_width = params.image_width;
         let samples_per_pixel = params.samples_per_pixel;
         let max_depth = params.max_depth;
-        let vfov = params.vfov;
-        let defocus_angle = params.defocus_angle;
         let background = params.background;
+        let sampling = params.sampling;
+        let heuristic = params.heuristic;
 
-        let image_height = (image_width as f64 / params.aspect_ratio) as u32;
+        let aspect_ratio = match params.projection {
+            Projection::Equirectangular => 2.0,
+            _ => params.aspect_ratio,
+        };
+        let image_height = (image_width as f64 / aspect_ratio) as u32;
         let image_height = if image_height < 1 { 1 } else { image_height };
 
         let pixel_samples_scale = 1.0 / samples_per_pixel as f64;
+        // Narrower filters would miss samples between the pixels
+        let filter_radius = params
+            .filter_radius
+            .unwrap_or(params.filter.default_radius())
+            .max(0.5);
 
-        let center = params.look_from;
-
-        // Viewport Dimensions
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * params.focus_dist;
-        let viewport_width = viewport_height * (image_width as f64 / image_height as f64);
-
-        // Calculate the u, v, w unit basis vectors for the camera coordinate frame.
-        let w = (params.look_from - params.look_at).unit_vector();
-        let u = (params.v_up.cross(w)).unit_vector();
-        let v = w.cross(u);
+        let optics = Optics {
+            image_width,
+            image_height,
+            projection: params.projection,
+            view_width: params.view_width,
+            eye_offset: match params.eye {
+                Eye::Center => 0.0,
+                Eye::Left => -0.5 * params.ipd,
+                Eye::Right => 0.5 * params.ipd,
+            },
+            convergence_dist: params.convergence_dist,
+            stereo_mode: params.stereo_mode,
+        };
+        let view = View::new(&optics, &params.keyframe(0.0));
 
-        // Viewport Vectors
-        let viewport_u = viewport_width * u; // Horizontal across the viewport, starting at the left
-        let viewport_v = viewport_height * -v; // Vertical across the viewport, starting at the top
+        let mut motion = params.motion.clone();
+        motion.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let defocus =
+            params.defocus_angle > 0.0 || motion.iter().any(|key| key.defocus_angle > 0.0);
 
-        // Viewport pixel delta vectors
-        let pixel_delta_u = viewport_u / image_width as f64;
-        let pixel_delta_v = viewport_v / image_height as f64;
-
-        // Find upper left pixel
-        let viewport_upper_left = // upper left corner of the viewport
-        center - (params.focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
-        let pixel_00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v); // First pixel is half a pixel delta from the top left corner
-
-        // Calculate the camera defocus disk basis vectors
-        let defocus_radius = params.focus_dist * (defocus_angle / 2.0).to_radians().tan();
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
+        let shutter = Shutter::new(
+            params.shutter_open,
+            params.shutter_close,
+            &params.shutter_curve,
+        );
 
         Self {
             image_width,
@@ -170,46 +753,130 @@ impl CameraCore {
 
             image_height,
             pixel_samples_scale,
-            center,
-            pixel_00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
+            filter: params.filter,
+            filter_radius,
+            spectral: params.spectral,
 
-            u,
-            v,
-            w,
+            view,
+            motion,
+            shutter,
 
-            defocus_angle,
-            defocus_disk_u,
-            defocus_disk_v,
+            defocus,
+            aperture: ApertureSampler::new(&params.aperture),
+            anamorphic_squeeze: params.anamorphic_squeeze,
+            vignetting: params.vignetting,
+
+            optics,
+            fisheye_fov: params.fisheye_fov,
 
             background,
+
+            sampling,
+            heuristic,
         }
     }
 
-    fn ray_color(&self, r: Ray, depth: u32, world: &Hittable) -> Color {
-        // Used to solve shadow acne problem, preventing rays from colliding with the same surface they just did
-        const SURFACE_HOLDOFF_DIST: f64 = 0.001;
+    ///
+    /// The camera's placement at `time`, holding still before the first keyframe and after the
+    /// last
+    ///
+    fn view_at(&self, time: f64) -> View {
+        let keys = &self.motion;
+        let next = keys.partition_point(|k| k.time <= time);
+        let key = if next == 0 {
+            keys[0]
+        } else if next == keys.len() {
+            keys[keys.len() - 1]
+        } else {
+            let (a, b) = (&keys[next - 1], &keys[next]);
+            a.lerp(b, (time - a.time) / (b.time - a.time))
+        };
+        View::new(&self.optics, &key)
+    }
 
-        if depth <= 0 {
+    ///
+    /// `bsdf_pdf` is the density the previous bounce sampled `r` with, when it followed a
+    /// material's distribution. Emission found by such rays is weighed against the chance of
+    /// sampling the same light directly. It is None for camera rays and specular bounces.
+    ///
+    fn ray_color(
+        &self,
+        r: Ray,
+        depth: u32,
+        world: &Hittable,
+        lights: &HittableList,
+        bsdf_pdf: Option<f64>,
+    ) -> Color {
+        if depth == 0 {
             // exceeded bounce limit, no more light gathered
             return Color::black();
         }
 
-        if let Some(rec) = world.hit(r, SURFACE_HOLDOFF_DIST..=INFINITY) {
+        rng::start_search();
+        if let Some(rec) = world.hit(r, SURFACE_HOLDOFF_DIST..=f64::INFINITY) {
             // We hit something, calculate based on emission and scatter colors
-            let emission_color = rec.mat.emitted(rec.u, rec.v, rec.p);
-            if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-                // Scatters and emits, combine them.
-                let scatter_color = attenuation * self.ray_color(scattered, depth - 1, world);
-                scatter_color + emission_color
-            } else {
+            let mut emission_color = r.uplift(rec.mat.emitted(rec.u, rec.v, rec.p));
+            if let Some(bsdf_pdf) = bsdf_pdf {
+                if emission_color != Color::black() {
+                    emission_color *= self.emission_weight(bsdf_pdf, r, lights);
+                }
+            }
+
+            let Some((mut attenuation, scatter)) = rec.mat.scatter(r, &rec) else {
                 // Doesn't scatter light, just emits
-                emission_color
+                return emission_color;
+            };
+
+            // A dispersive material sends each wavelength its own way, so only the hero
+            // wavelength can follow the direction it picked
+            let mut r = r;
+            if let Some(wavelengths) = r.wavelengths() {
+                if rec.mat.is_dispersive() && !wavelengths.is_hero_only() {
+                    let (hero, weight) = wavelengths.terminate_secondary();
+                    attenuation = attenuation * weight;
+                    r = r.with_wavelengths(Some(hero));
+                }
             }
+
+            let bsdf_pdf = match scatter {
+                Scatter::Specular(scattered) => {
+                    // Follow the only possible direction, no need to weigh it
+                    let scattered = scattered.with_wavelengths(r.wavelengths());
+                    let scatter_color =
+                        attenuation * self.ray_color(scattered, depth - 1, world, lights, None);
+                    return scatter_color + emission_color;
+                }
+                Scatter::Pdf(pdf) => pdf,
+            };
+
+            if lights.is_empty() || self.sampling == Sampling::Bsdf {
+                let scatter_color =
+                    self.sample_bsdf(r, &rec, &bsdf_pdf, depth, world, lights, false);
+                return attenuation * scatter_color + emission_color;
+            }
+
+            let scatter_color = match self.sampling {
+                Sampling::Mixture => {
+                    // Half of the rays go straight for a light, half follow the material
+                    let light_pdf = Pdf::Lights {
+                        lights,
+                        origin: rec.p,
+                        time: r.time(),
+                    };
+                    let pdf = Pdf::mixture(light_pdf, bsdf_pdf);
+                    self.sample_bsdf(r, &rec, &pdf, depth, world, lights, false)
+                }
+                _ => {
+                    // Direct light from a light sample, everything else from a material sample
+                    self.sample_light(r, &rec, &bsdf_pdf, world, lights)
+                        + self.sample_bsdf(r, &rec, &bsdf_pdf, depth, world, lights, true)
+                }
+            };
+
+            attenuation * scatter_color + emission_color
         } else {
             // If we didn't hit anything, return the background
-            if let Some(background) = self.background {
+            let background = if let Some(background) = self.background {
                 background
             } else {
                 // Basic gradient. This is expected to have a small horizontal gradient to go with the vertical gradient,
@@ -218,49 +885,259 @@ impl CameraCore {
                 let unit_direction = r.direction().unit_vector();
                 let a = 0.5 * (unit_direction.y() + 1.0); // convert y coordinate to between 0 and 1
                 (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            };
+            r.uplift(background)
+        }
+    }
+
+    ///
+    /// Light arriving along a ray scattered in a direction drawn from `pdf`, scaled by the
+    /// material's scattering density over the density of drawing that direction. With
+    /// `weigh_emission`, emission found by the scattered ray is weighed by `emission_weight`.
+    ///
+    #[allow(clippy::too_many_arguments)]
+    fn sample_bsdf(
+        &self,
+        r: Ray,
+        rec: &HitRecord,
+        pdf: &Pdf,
+        depth: u32,
+        world: &Hittable,
+        lights: &HittableList,
+        weigh_emission: bool,
+    ) -> Color {
+        let scattered =
+            Ray::with_time(rec.p, pdf.generate(), r.time()).with_wavelengths(r.wavelengths());
+        let pdf_value = pdf.value(*scattered.direction());
+        if pdf_value <= 0.0 || pdf_value.is_nan() {
+            return Color::black();
+        }
+
+        let scattering_pdf = rec.mat.scattering_pdf(r, rec, scattered);
+        if scattering_pdf <= 0.0 {
+            return Color::black();
+        }
+
+        let tint = rec.mat.scattering_tint(r, rec, scattered);
+        let bsdf_pdf = weigh_emission.then_some(pdf_value);
+        scattering_pdf * tint * self.ray_color(scattered, depth - 1, world, lights, bsdf_pdf)
+            / pdf_value
+    }
+
+    ///
+    /// Emission arriving from a direction towards a random point on `lights`, scaled by the
+    /// material's scattering density over the density of picking that direction, and weighed
+    /// against `bsdf_pdf` sampling the same direction.
+    ///
+    fn sample_light(
+        &self,
+        r: Ray,
+        rec: &HitRecord,
+        bsdf_pdf: &Pdf,
+        world: &Hittable,
+        lights: &HittableList,
+    ) -> Color {
+        let shadow_ray = Ray::with_time(rec.p, lights.random(rec.p, r.time()), r.time());
+        let light_pdf = lights.pdf_value(shadow_ray);
+        if light_pdf <= 0.0 {
+            return Color::black();
+        }
+
+        let scattering_pdf = rec.mat.scattering_pdf(r, rec, shadow_ray);
+        if scattering_pdf <= 0.0 {
+            return Color::black();
+        }
+
+        // Whatever the shadow ray hits first is what the light sample sees
+        rng::start_search();
+        let Some(light_rec) = world.hit(shadow_ray, SURFACE_HOLDOFF_DIST..=f64::INFINITY) else {
+            return Color::black();
+        };
+        let emission = r.uplift(light_rec.mat.emitted(light_rec.u, light_rec.v, light_rec.p));
+
+        let weight = match self.sampling {
+            Sampling::Mis => self
+                .heuristic
+                .weight(light_pdf, bsdf_pdf.value(*shadow_ray.direction())),
+            _ => 1.0,
+        };
+
+        let tint = rec.mat.scattering_tint(r, rec, shadow_ray);
+        weight * scattering_pdf * tint * emission / light_pdf
+    }
+
+    ///
+    /// Weight of emission found by `r`, which was sampled from a material's distribution with
+    /// density `bsdf_pdf`, given that the lights might have been sampled directly instead
+    ///
+    fn emission_weight(&self, bsdf_pdf: f64, r: Ray, lights: &HittableList) -> f64 {
+        match self.sampling {
+            Sampling::Light => {
+                // Sampled lights were already counted by their light sample
+                if lights.pdf_value(r) > 0.0 {
+                    0.0
+                } else {
+                    1.0
+                }
             }
+            Sampling::Mis => self.heuristic.weight(bsdf_pdf, lights.pdf_value(r)),
+            Sampling::Bsdf | Sampling::Mixture => 1.0,
         }
     }
 
     ///
-    /// Constructs a camera ray originating from the defocus disk and directed at a randomly
-    /// sampled point around the pixel at (i, j)
+    /// Constructs a camera ray through the point `offset` from the center of the pixel at (i, j),
+    /// at a time drawn from the shutter. Perspective and orthographic rays start on the defocus disk.
+    /// Returns None for pixels outside the image circle of a fisheye, and for rays blocked by the
+    /// lens barrel.
     ///
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let offset = sample_square();
-        let pixel_sample = self.pixel_00_loc
-            + ((i as f64 + offset.x()) * self.pixel_delta_u)
-            + ((j as f64 + offset.y()) * self.pixel_delta_v);
+    fn get_ray(&self, i: u32, j: u32, offset: Vec3) -> Option<Ray> {
+        let disk = self.defocus.then(|| self.aperture.sample());
+        let ray_time = self.shutter.sample();
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+        let moved;
+        let view = if self.motion.is_empty() {
+            &self.view
         } else {
-            self.defocus_disk_sample()
+            moved = self.view_at(ray_time);
+            &moved
         };
 
-        let ray_dir = pixel_sample - ray_origin;
-        let ray_time = random::<f64>();
+        let pixel_sample = view.pixel_00_loc
+            + ((i as f64 + offset.x()) * view.pixel_delta_u)
+            + ((j as f64 + offset.y()) * view.pixel_delta_v);
 
-        Ray::with_time(ray_origin, ray_dir, ray_time)
+        // Position on the image, x from -1 at the left edge to 1 at the right, y up
+        let x = 2.0 * (i as f64 + 0.5 + offset.x()) / self.image_width as f64 - 1.0;
+        let y = (1.0 - 2.0 * (j as f64 + 0.5 + offset.y()) / self.image_height as f64)
+            * self.image_height as f64
+            / self.image_width as f64;
+
+        let disk_offset = match disk {
+            Some(p) => {
+                if self.vignetted(p, x, y) {
+                    return None;
+                }
+                p.x() / self.anamorphic_squeeze * view.defocus_disk_u + p.y() * view.defocus_disk_v
+            }
+            None => Vec3::new(0.0, 0.0, 0.0),
+        };
+
+        let (ray_origin, ray_dir) = match self.optics.projection {
+            Projection::Perspective => {
+                let ray_origin = view.center + disk_offset;
+                (ray_origin, pixel_sample - ray_origin)
+            }
+
+            Projection::Orthographic => {
+                // Every pixel has a lens of its own, focused straight ahead
+                let focus = pixel_sample - view.focus_dist * view.w;
+                let ray_origin = pixel_sample + disk_offset;
+                (ray_origin, focus - ray_origin)
+            }
+
+            _ => {
+                let (forward, right, up) = self.direction(x, y)?;
+                let ray_dir = forward * -view.w + right * view.u + up * view.v;
+
+                // For omnidirectional stereo, the eye sits to the side of the horizontal
+                // direction, at a distance shrinking with its cosine towards the poles
+                let eye = match self.optics.projection {
+                    Projection::Equirectangular => forward * view.u + right * view.w,
+                    _ => Vec3::new(0.0, 0.0, 0.0),
+                };
+                (view.center + self.optics.eye_offset * eye, ray_dir)
+            }
+        };
+
+        Some(Ray::with_time(ray_origin, ray_dir, ray_time))
     }
 
     ///
-    /// Returns a random point in the camera defocus disk.
+    /// Whether the point `p` of the aperture is hidden behind the lens barrel, for the image
+    /// point (x, y). Away from the center the barrel's opening is seen at an angle, a circle
+    /// sliding off the aperture which leaves the cat-eye shape where the two overlap.
+    ///
+    fn vignetted(&self, p: Vec3, x: f64, y: f64) -> bool {
+        if self.vignetting <= 0.0
+            || !matches!(
+                self.optics.projection,
+                Projection::Perspective | Projection::Orthographic
+            )
+        {
+            return false;
+        }
+        let half_diagonal =
+            (1.0 + (self.image_height as f64 / self.image_width as f64).powi(2)).sqrt();
+        let shift = self.vignetting / half_diagonal;
+        (p.x() - shift * x).powi(2) + (p.y() - shift * y).powi(2) > 1.0
+    }
+
     ///
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
-        self.center + (p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v)
+    /// Direction through the point (x, y) of the image of a fisheye or panoramic projection, as
+    /// components forward, right and up. x goes from -1 to 1 across the image width, and y
+    /// covers the same scale upwards from the center.
+    ///
+    fn direction(&self, x: f64, y: f64) -> Option<(f64, f64, f64)> {
+        if self.optics.projection == Projection::Equirectangular {
+            let (longitude, latitude) = (x * PI, y * PI);
+            return Some((
+                latitude.cos() * longitude.cos(),
+                latitude.cos() * longitude.sin(),
+                latitude.sin(),
+            ));
+        }
+
+        // Angle from the view direction, from the distance to the center of the image
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        if r == 0.0 {
+            return Some((1.0, 0.0, 0.0));
+        }
+        let half_fov = 0.5 * self.fisheye_fov.to_radians();
+        let theta = match self.optics.projection {
+            Projection::FisheyeEquisolid => 2.0 * (r * (0.5 * half_fov).sin()).asin(),
+            _ => r * half_fov,
+        };
+        let sin_theta = theta.sin();
+        Some((theta.cos(), sin_theta * x / r, sin_theta * y / r))
     }
 }
 
+// Used to solve shadow acne problem, preventing rays from colliding with the same surface they just did
+const SURFACE_HOLDOFF_DIST: f64 = 0.001;
+
 static SQUARE_DIST: once_cell::sync::Lazy<Uniform<f64>> =
     once_cell::sync::Lazy::new(|| Uniform::from(-0.5..0.5));
 
 fn sample_square() -> Vec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng::thread_rng();
     Vec3::new(
         SQUARE_DIST.sample(&mut rng),
         SQUARE_DIST.sample(&mut rng),
         0.0,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_shutter_curve_needs_some_area() {
+        assert!(ShutterCurve::custom(vec![]).is_err());
+        assert!(ShutterCurve::custom(vec![1.0]).is_err());
+        assert!(ShutterCurve::custom(vec![0.0, 0.0, 0.0]).is_err());
+        assert!(ShutterCurve::custom(vec![1.0, -1.0]).is_err());
+        assert!(ShutterCurve::custom(vec![1.0, f64::NAN]).is_err());
+        assert!("0,0".parse::<ShutterCurve>().is_err());
+
+        let curve = ShutterCurve::custom(vec![0.0, 1.0]).unwrap();
+        let shutter = Shutter::new(2.0, 3.0, &curve);
+        for _ in 0..100 {
+            assert!((2.0..=3.0).contains(&shutter.sample()));
+        }
+    }
+}
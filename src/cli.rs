@@ -0,0 +1,550 @@
+use std::{ops::RangeInclusive, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{builder::PossibleValuesParser, Parser};
+use image::ImageFormat;
+
+use raytracer_v2::{
+    animation::Animation,
+    aperture::Aperture,
+    camera::{Camera, MisHeuristic, Projection, Sampling, ShutterCurve, StereoMode},
+    color::Color,
+    filter::Filter,
+    hittable::{bvh::BvhSplit, hittable_list::HittableList, Hittable},
+    output::{OutputFormat, StereoLayout},
+    rng,
+    scene::{find_camera, find_scene, CAMERAS, SCENES},
+    scene_file,
+    texture::image::Image,
+    tonemap::{ToneMap, ToneMapping, Transfer},
+};
+
+#[derive(Parser)]
+#[command(about = "A software raytracer based on the Ray Tracing in One Weekend series")]
+pub struct Cli {
+    /// Built-in scene to render, see --list
+    #[arg(
+        default_value = "book2_final",
+        value_parser = PossibleValuesParser::new(SCENES.iter().map(|s| s.name)),
+        hide_possible_values = true,
+    )]
+    scene: String,
+
+    /// Render a TOML scene file instead of a built-in scene
+    #[arg(short = 'f', long, value_name = "PATH", conflicts_with_all = ["scene", "camera"])]
+    scene_file: Option<PathBuf>,
+
+    /// Built-in camera to use instead of the scene's own, see --list
+    #[arg(
+        short,
+        long,
+        value_parser = PossibleValuesParser::new(CAMERAS.iter().map(|c| c.name)),
+        hide_possible_values = true,
+    )]
+    camera: Option<String>,
+
+    /// List the built-in scenes and cameras, then exit
+    #[arg(short, long)]
+    list: bool,
+
+    /// Path of the rendered image, or the directory to write the frames of an animation to.
+    /// Defaults to output/test.png, or output/frames for animations
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Image format, such as png or jpeg, or exr, exr-float, hdr or pfm to keep the full range of
+    /// brightness. Guessed from the output path if not given
+    #[arg(long)]
+    format: Option<OutputFormat>,
+
+    /// Stops to brighten the image by before tone mapping, or darken when negative
+    #[arg(
+        long,
+        value_name = "EV",
+        default_value_t = 0.0,
+        allow_negative_numbers = true
+    )]
+    exposure: f64,
+
+    /// Tone mapping operator for 8-bit images: clamp, reinhard, extended_reinhard, hable or aces
+    #[arg(long, value_name = "OPERATOR", default_value = "clamp")]
+    tone_map: ToneMap,
+
+    /// Luminance that extended_reinhard maps to white. Defaults to the brightest pixel's
+    #[arg(long, value_parser = parse_positive)]
+    white_point: Option<f64>,
+
+    /// Transfer function for 8-bit images: gamma2 (a square root) or srgb
+    #[arg(long, default_value = "gamma2")]
+    transfer: Transfer,
+
+    /// Rendered image width in pixel count
+    #[arg(short = 'w', long, value_parser = clap::value_parser!(u32).range(1..))]
+    image_width: Option<u32>,
+
+    /// Number of samples for each pixel
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    samples_per_pixel: Option<u32>,
+
+    /// Maximum number of ray bounces into the scene
+    #[arg(short = 'd', long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_depth: Option<u32>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_vfov)]
+    vfov: Option<f64>,
+
+    /// Ratio of image width over height, as a number or in the form 16:9
+    #[arg(short, long, value_parser = parse_aspect_ratio)]
+    aspect_ratio: Option<f64>,
+
+    /// Camera projection: perspective, orthographic, fisheye_equidistant, fisheye_equisolid or
+    /// equirectangular
+    #[arg(long)]
+    projection: Option<Projection>,
+
+    /// Width of the orthographic projection's view, in world units
+    #[arg(long, value_parser = parse_positive)]
+    view_width: Option<f64>,
+
+    /// Field of view of the fisheye projections across the image width, in degrees
+    #[arg(long, value_name = "DEGREES", value_parser = parse_fisheye_fov)]
+    fisheye_fov: Option<f64>,
+
+    /// Render a stereo pair, as side_by_side or top_bottom in one image, or as separate images
+    /// with _left and _right added to the file name
+    #[arg(long, value_name = "LAYOUT")]
+    stereo: Option<StereoLayout>,
+
+    /// Distance between the eyes of a stereo pair, in world units
+    #[arg(long, value_name = "DISTANCE", value_parser = parse_positive)]
+    ipd: Option<f64>,
+
+    /// Distance at which the eyes' views meet. Defaults to the focus distance
+    #[arg(long, value_name = "DISTANCE", value_parser = parse_positive)]
+    convergence: Option<f64>,
+
+    /// How the eyes are aimed: off_axis, or toe_in to turn them towards each other
+    #[arg(long, value_name = "MODE")]
+    stereo_mode: Option<StereoMode>,
+
+    /// Number of blades of a polygonal aperture, which out of focus highlights take the shape of
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(3..))]
+    aperture_blades: Option<u32>,
+
+    /// Rotation of the polygonal aperture, counterclockwise in degrees
+    #[arg(long, value_name = "DEGREES")]
+    aperture_rotation: Option<f64>,
+
+    /// Grayscale image to shape the aperture with, brighter parts letting more light through
+    #[arg(long, value_name = "PATH", conflicts_with = "aperture_blades")]
+    aperture_image: Option<PathBuf>,
+
+    /// How many times narrower the aperture is than it is tall, for anamorphic bokeh
+    #[arg(long, value_name = "FACTOR", value_parser = parse_positive)]
+    anamorphic_squeeze: Option<f64>,
+
+    /// How far the lens barrel cuts into the aperture towards the corners, for cat-eye bokeh.
+    /// 0 for none, up to 2 to close it completely at the corners
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_non_negative)]
+    vignetting: Option<f64>,
+
+    /// Pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos
+    #[arg(long)]
+    filter: Option<Filter>,
+
+    /// Reach of the pixel filter, in pixels. Defaults to 0.5 for box, 1 for tent, 1.5 for
+    /// gaussian, 2 for mitchell and 3 for lanczos
+    #[arg(long, value_name = "PIXELS", value_parser = parse_filter_radius)]
+    filter_radius: Option<f64>,
+
+    /// Trace wavelengths of light instead of RGB, so that dispersive glass splits white light
+    /// into colors
+    #[arg(long)]
+    spectral: bool,
+
+    /// How rays scattered off non-specular materials are chosen: bsdf, light, mixture or mis
+    #[arg(long)]
+    sampling: Option<Sampling>,
+
+    /// Weighting used by --sampling mis: power or balance
+    #[arg(long)]
+    heuristic: Option<MisHeuristic>,
+
+    /// Ray time at which the shutter opens. Animations open it for each frame instead.
+    #[arg(
+        long,
+        value_name = "TIME",
+        allow_negative_numbers = true,
+        conflicts_with = "frames"
+    )]
+    shutter_open: Option<f64>,
+
+    /// Ray time at which the shutter closes. Moving spheres move over times 0 to 1
+    #[arg(
+        long,
+        value_name = "TIME",
+        allow_negative_numbers = true,
+        conflicts_with = "frames"
+    )]
+    shutter_close: Option<f64>,
+
+    /// How exposure is spread over the time the shutter is open: box, triangle, or comma
+    /// separated weights at evenly spaced times
+    #[arg(long, value_name = "CURVE")]
+    shutter_curve: Option<ShutterCurve>,
+
+    /// Render an image sequence of this many frames, frame_0001.png and so on, over ray times 0 to
+    /// 1 or the scene file's [animation] times
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    frames: Option<u32>,
+
+    /// First frame of the animation to render, counting from 1
+    #[arg(long, value_name = "FRAME", value_parser = clap::value_parser!(u32).range(1..))]
+    first_frame: Option<u32>,
+
+    /// Last frame of the animation to render
+    #[arg(long, value_name = "FRAME", value_parser = clap::value_parser!(u32).range(1..))]
+    last_frame: Option<u32>,
+
+    /// Share of each frame of an animation the shutter is open, in degrees out of 360
+    #[arg(long, value_name = "DEGREES")]
+    shutter_angle: Option<f64>,
+
+    /// Don't render the frames of an animation whose image already exists
+    #[arg(long)]
+    skip_existing: bool,
+
+    /// Rebuild every BVH in the scene, dividing objects by: median or sah (surface area
+    /// heuristic). Overrides the split of scene file groups. BVHs split at the median otherwise.
+    #[arg(long, value_name = "SPLIT")]
+    bvh: Option<BvhSplit>,
+
+    /// Seed for the random numbers used to render and to lay out random scenes. Renders with the
+    /// same seed and settings are identical.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Print the estimated intersection cost of the world, and the shape of all of its BVHs
+    /// together, before rendering
+    #[arg(long)]
+    stats: bool,
+
+    /// Background color as r,g,b, or "sky" for the default gradient
+    #[arg(short, long, value_parser = parse_background)]
+    background: Option<Background>,
+}
+
+#[derive(Clone, Copy)]
+struct Background(Option<Color>);
+
+///
+/// Everything needed to render, as chosen on the command line
+///
+pub struct RenderJob {
+    pub camera: Camera,
+    pub world: Hittable,
+    pub lights: HittableList,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    pub tone_mapping: ToneMapping,
+    pub animation: Option<AnimationJob>,
+    pub stereo: Option<StereoLayout>,
+}
+
+///
+/// The frames of an image sequence to render
+///
+pub struct AnimationJob {
+    pub animation: Animation,
+    pub frames: RangeInclusive<u32>, // Frames to render, counting from 1
+    pub skip_existing: bool,         // Leave frames whose image exists alone
+}
+
+impl Cli {
+    ///
+    /// Returns None when there is nothing to render, such as after --list
+    ///
+    pub fn into_job(self) -> Result<Option<RenderJob>> {
+        if self.list {
+            print_list();
+            return Ok(None);
+        }
+
+        rng::set_seed(self.seed);
+
+        let (mut camera, world, lights, animation) = match &self.scene_file {
+            Some(path) => {
+                let scene = scene_file::load(path)?;
+                (scene.camera, scene.world, scene.lights, scene.animation)
+            }
+            None => {
+                let scene = find_scene(&self.scene).expect("Scene names are checked by clap");
+                let camera = match &self.camera {
+                    Some(name) => find_camera(name).expect("Camera names are checked by clap"),
+                    None => &scene.camera,
+                };
+                ((camera.camera)(), (scene.world)(), (scene.lights)(), None)
+            }
+        };
+        let world = match self.bvh {
+            Some(split) => world.with_bvh_split(split),
+            None => world,
+        };
+
+        let animation = match (self.frames, animation) {
+            (Some(frames), Some(animation)) => Some(Animation {
+                frames,
+                ..animation
+            }),
+            (Some(frames), None) => Some(Animation::new(frames)),
+            (None, animation) => animation,
+        };
+        let animation = animation
+            .map(|animation| self.animation_job(animation))
+            .transpose()?;
+        if animation.is_none()
+            && (self.first_frame.is_some()
+                || self.last_frame.is_some()
+                || self.shutter_angle.is_some()
+                || self.skip_existing)
+        {
+            bail!("Frame options need an animation, see --frames");
+        }
+
+        let output = self.output.clone().unwrap_or_else(|| match animation {
+            Some(_) => PathBuf::from("output/frames"),
+            None => PathBuf::from("output/test.png"),
+        });
+        let format = match (self.format, &animation) {
+            (Some(format), _) => format,
+            (None, Some(_)) => OutputFormat::Ldr(ImageFormat::Png),
+            (None, None) => OutputFormat::from_path(&output).map_err(|_| {
+                anyhow!(
+                    "Cannot tell the image format of {}, use --format to pick one",
+                    output.display()
+                )
+            })?,
+        };
+
+        if self.stats {
+            println!("Estimated intersection cost: {:.2}", world.cost());
+            if let Some(stats) = world.bvh_stats() {
+                println!("BVH: {stats}");
+            }
+        }
+
+        if let Some(image_width) = self.image_width {
+            camera.image_width = image_width;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            camera.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(projection) = self.projection {
+            camera.projection = projection;
+        }
+        if let Some(view_width) = self.view_width {
+            camera.view_width = view_width;
+        }
+        if let Some(fisheye_fov) = self.fisheye_fov {
+            camera.fisheye_fov = fisheye_fov;
+        }
+        if let Some(ipd) = self.ipd {
+            camera.ipd = ipd;
+        }
+        if let Some(convergence) = self.convergence {
+            camera.convergence_dist = Some(convergence);
+        }
+        if let Some(stereo_mode) = self.stereo_mode {
+            camera.stereo_mode = stereo_mode;
+        }
+        if let Some(blades) = self.aperture_blades {
+            camera.aperture = Aperture::Polygon {
+                blades,
+                rotation: 0.0,
+            };
+        }
+        if let Some(path) = &self.aperture_image {
+            camera.aperture = Aperture::Texture(Image::load(path)?.into());
+        }
+        if let Some(angle) = self.aperture_rotation {
+            match &mut camera.aperture {
+                Aperture::Polygon { rotation, .. } => *rotation = angle,
+                _ => bail!("--aperture-rotation needs a polygonal aperture, see --aperture-blades"),
+            }
+        }
+        camera
+            .aperture
+            .validate()
+            .context("Cannot use the aperture")?;
+        if let Some(anamorphic_squeeze) = self.anamorphic_squeeze {
+            camera.anamorphic_squeeze = anamorphic_squeeze;
+        }
+        if let Some(vignetting) = self.vignetting {
+            camera.vignetting = vignetting;
+        }
+        if let Some(filter) = self.filter {
+            camera.filter = filter;
+        }
+        if let Some(filter_radius) = self.filter_radius {
+            camera.filter_radius = Some(filter_radius);
+        }
+        if self.spectral {
+            camera.spectral = true;
+        }
+        if let Some(sampling) = self.sampling {
+            camera.sampling = sampling;
+        }
+        if let Some(heuristic) = self.heuristic {
+            camera.heuristic = heuristic;
+        }
+        if let Some(Background(background)) = self.background {
+            camera.background = background;
+        }
+        if let Some(shutter_open) = self.shutter_open {
+            camera.shutter_open = shutter_open;
+        }
+        if let Some(shutter_close) = self.shutter_close {
+            camera.shutter_close = shutter_close;
+        }
+        if let Some(shutter_curve) = self.shutter_curve {
+            camera.shutter_curve = shutter_curve;
+        }
+        if camera.shutter_close < camera.shutter_open {
+            bail!("The shutter closes before it opens");
+        }
+
+        Ok(Some(RenderJob {
+            camera,
+            world,
+            lights,
+            output,
+            format,
+            tone_mapping: ToneMapping {
+                exposure: self.exposure,
+                operator: self.tone_map,
+                white_point: self.white_point,
+                transfer: self.transfer,
+            },
+            animation,
+            stereo: self.stereo,
+        }))
+    }
+
+    fn animation_job(&self, mut animation: Animation) -> Result<AnimationJob> {
+        // Each frame opens and closes the shutter itself
+        if self.shutter_open.is_some() || self.shutter_close.is_some() {
+            bail!("An animation's shutter is set by --shutter-angle, not --shutter-open or -close");
+        }
+        if let Some(shutter_angle) = self.shutter_angle {
+            animation.shutter_angle = shutter_angle;
+        }
+        animation.validate()?;
+
+        let first = self.first_frame.unwrap_or(1);
+        let last = self.last_frame.unwrap_or(animation.frames);
+        if last > animation.frames {
+            bail!(
+                "The last frame is {last}, but the animation only has {} frames",
+                animation.frames
+            );
+        }
+        if first > last {
+            bail!("The first frame comes after the last frame");
+        }
+
+        Ok(AnimationJob {
+            animation,
+            frames: first..=last,
+            skip_existing: self.skip_existing,
+        })
+    }
+}
+
+fn print_list() {
+    println!("Scenes (default camera):");
+    for scene in SCENES {
+        println!("  {:<40} {}", scene.name, scene.camera.name);
+    }
+    println!();
+    println!("Cameras:");
+    for camera in CAMERAS {
+        println!("  {}", camera.name);
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(_) => Err(format!("`{s}` is not a number")),
+    }
+}
+
+fn parse_non_negative(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if v >= 0.0 && v.is_finite() => Ok(v),
+        Ok(_) => Err("cannot be negative".to_string()),
+        Err(_) => Err(format!("`{s}` is not a number")),
+    }
+}
+
+fn parse_vfov(s: &str) -> Result<f64, String> {
+    let vfov = parse_positive(s)?;
+    if vfov < 180.0 {
+        Ok(vfov)
+    } else {
+        Err("must be less than 180 degrees".to_string())
+    }
+}
+
+fn parse_fisheye_fov(s: &str) -> Result<f64, String> {
+    let fov = parse_positive(s)?;
+    if fov <= 360.0 {
+        Ok(fov)
+    } else {
+        Err("must be at most 360 degrees".to_string())
+    }
+}
+
+fn parse_filter_radius(s: &str) -> Result<f64, String> {
+    let radius = parse_positive(s)?;
+    if radius >= 0.5 {
+        Ok(radius)
+    } else {
+        Err("must be at least 0.5 pixels".to_string())
+    }
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<f64, String> {
+    match s.split_once(':') {
+        Some((w, h)) => Ok(parse_positive(w)? / parse_positive(h)?),
+        None => parse_positive(s),
+    }
+}
+
+fn parse_background(s: &str) -> Result<Background, String> {
+    if s == "sky" {
+        return Ok(Background(None));
+    }
+
+    let components = s
+        .split(',')
+        .map(|c| c.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("`{s}` is not a color, expected r,g,b or \"sky\""))?;
+    match components[..] {
+        [r, g, b] if r >= 0.0 && g >= 0.0 && b >= 0.0 => Ok(Background(Some(Color::new(r, g, b)))),
+        [_, _, _] => Err("color components cannot be negative".to_string()),
+        _ => Err(format!("`{s}` is not a color, expected r,g,b or \"sky\"")),
+    }
+}
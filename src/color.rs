@@ -4,13 +4,12 @@ use std::{
     ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub},
 };
 
-use crate::interval::{Clamp, Interval};
-use image::{Rgb, RgbImage};
-use rand::{
-    distributions::{Distribution, Uniform},
-    random,
-    rngs::ThreadRng,
+use crate::{
+    interval::{Clamp, Interval},
+    rng::{random, ThreadRng},
 };
+use image::Rgb;
+use rand::distributions::{Distribution, Uniform};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Color(f64, f64, f64);
@@ -51,6 +50,13 @@ impl Color {
     pub fn b(&self) -> f64 {
         self.2
     }
+
+    ///
+    /// Perceived brightness of a linear sRGB color
+    ///
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2
+    }
 }
 
 fn linear_to_gamma(linear_component: f64) -> f64 {
@@ -61,33 +67,30 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
     }
 }
 
-pub fn write_color(img: &mut RgbImage, color: &Color, u: u32, v: u32) {
-    img.put_pixel(u, v, color.into());
-}
-
-pub fn write_row(img: &mut RgbImage, row: &[Color], v: u32) {
-    for (u, color) in row.iter().enumerate() {
-        write_color(img, color, u as u32, v);
-    }
-}
-
 impl From<&Color> for Rgb<u8> {
     fn from(value: &Color) -> Self {
-        const SCALE_FACTOR: f64 = 256.0;
-        const INTENSITY: Interval = 0.0..=0.999;
-
         let r = linear_to_gamma(value.r());
         let g = linear_to_gamma(value.g());
         let b = linear_to_gamma(value.b());
 
-        let ir = (SCALE_FACTOR * INTENSITY.clamp(r)) as u8;
-        let ig = (SCALE_FACTOR * INTENSITY.clamp(g)) as u8;
-        let ib = (SCALE_FACTOR * INTENSITY.clamp(b)) as u8;
-
-        Self([ir, ig, ib])
+        quantize(Color(r, g, b))
     }
 }
 
+///
+/// Converts a color already encoded for display to 8 bits per channel, clipping it to 0..=1
+///
+pub fn quantize(encoded: Color) -> Rgb<u8> {
+    const SCALE_FACTOR: f64 = 256.0;
+    const INTENSITY: Interval = 0.0..=0.999;
+
+    let ir = (SCALE_FACTOR * INTENSITY.clamp(encoded.r())) as u8;
+    let ig = (SCALE_FACTOR * INTENSITY.clamp(encoded.g())) as u8;
+    let ib = (SCALE_FACTOR * INTENSITY.clamp(encoded.b())) as u8;
+
+    Rgb([ir, ig, ib])
+}
+
 impl From<crate::vec3::Vec3> for Color {
     fn from(value: crate::vec3::Vec3) -> Self {
         Self(value.x(), value.y(), value.z())
@@ -0,0 +1,152 @@
+//!
+//! Pixel reconstruction filters, which weigh how much each sample counts towards the pixels
+//! around it. Wider filters than the box smooth away aliasing on high contrast edges, at the
+//! cost of some sharpness.
+//!
+
+use std::{f64::consts::PI, str::FromStr};
+
+use serde::{de::IntoDeserializer, Deserialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// Every sample within the radius counts the same, the plain average of each pixel's samples
+    #[default]
+    Box,
+
+    /// Weight falling linearly to 0 at the radius
+    Tent,
+
+    /// A bell curve cut off at the radius, three standard deviations out
+    Gaussian,
+
+    ///
+    /// The Mitchell-Netravali cubic with B = C = 1/3, which keeps edges sharper than the
+    /// Gaussian with little ringing
+    ///
+    Mitchell,
+
+    /// A sinc windowed by a wider sinc, the sharpest of the filters but prone to ringing
+    Lanczos,
+}
+
+impl Filter {
+    ///
+    /// Radius in pixels that the filter is designed for
+    ///
+    pub fn default_radius(self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+            Filter::Lanczos => 3.0,
+        }
+    }
+
+    ///
+    /// Weight of a sample `x` pixels away from a pixel center along one axis, 0 outside of
+    /// `radius`. Mitchell and Lanczos weights can be negative.
+    ///
+    pub fn weight(self, x: f64, radius: f64) -> f64 {
+        let x = x.abs();
+        if x >= radius {
+            return 0.0;
+        }
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => 1.0 - x / radius,
+            Filter::Gaussian => {
+                let gaussian = |x: f64| (-4.5 * (x / radius).powi(2)).exp();
+                gaussian(x) - gaussian(radius)
+            }
+            Filter::Mitchell => mitchell(2.0 * x / radius),
+            Filter::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+///
+/// The Mitchell-Netravali cubic for `x` in 0..2
+///
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let (x2, x3) = (x * x, x * x * x);
+    if x < 1.0 {
+        ((12.0 - 9.0 * B - 6.0 * C) * x3 + (-18.0 + 12.0 * B + 6.0 * C) * x2 + (6.0 - 2.0 * B))
+            / 6.0
+    } else {
+        ((-B - 6.0 * C) * x3
+            + (6.0 * B + 30.0 * C) * x2
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C))
+            / 6.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 5] = [
+        Filter::Box,
+        Filter::Tent,
+        Filter::Gaussian,
+        Filter::Mitchell,
+        Filter::Lanczos,
+    ];
+
+    #[test]
+    fn weight_vanishes_at_the_radius() {
+        for filter in FILTERS {
+            let radius = filter.default_radius();
+            for x in [radius, -radius, radius + 0.1] {
+                assert_eq!(filter.weight(x, radius), 0.0, "{filter:?} at {x}");
+            }
+            // The weights that fade out do so continuously
+            if filter != Filter::Box {
+                let near = filter.weight(radius - 1e-6, radius);
+                assert!(near.abs() < 1e-4, "{filter:?} jumps to 0 at the radius");
+            }
+        }
+    }
+
+    #[test]
+    fn mitchell_weights_sum_to_one() {
+        // With B + 2C = 1, the weights of samples a pixel apart add up to 1 wherever they start
+        for i in 0..20 {
+            let x = i as f64 / 20.0;
+            let sum: f64 = (-2..=2)
+                .map(|k| Filter::Mitchell.weight(x + k as f64, 2.0))
+                .sum();
+            assert!((sum - 1.0).abs() < 1e-12, "{sum} at {x}");
+        }
+    }
+
+    #[test]
+    fn lanczos_is_one_at_the_center() {
+        assert_eq!(Filter::Lanczos.weight(0.0, 3.0), 1.0);
+        // and 0 at the other pixel centers, like the sinc it windows
+        for x in [1.0, 2.0] {
+            assert!(Filter::Lanczos.weight(x, 3.0).abs() < 1e-15);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use image::{Rgb, RgbImage};
+
+use crate::{
+    color::{quantize, Color},
+    filter::Filter,
+    tonemap::ToneMapping,
+};
+
+///
+/// A rendered image, holding the linear color of every pixel in row-major order, starting at
+/// the top left.
+///
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    ///
+    /// Creates an all black image
+    ///
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::black(); width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+
+    ///
+    /// Replaces row `y` with `row`, which must be exactly one image width long
+    ///
+    pub fn set_row(&mut self, y: u32, row: &[Color]) {
+        let start = self.index(0, y);
+        self.pixels[start..start + self.width as usize].copy_from_slice(row);
+    }
+
+    ///
+    /// Joins this image and `right` side by side. They must be the same height.
+    ///
+    pub fn beside(&self, right: &Framebuffer) -> Framebuffer {
+        assert_eq!(
+            self.height, right.height,
+            "Images side by side need the same height"
+        );
+        let mut joined = Framebuffer::new(self.width + right.width, self.height);
+        for y in 0..self.height {
+            let start = joined.index(0, y);
+            let (left_row, right_row) = (self.row(y), right.row(y));
+            joined.pixels[start..start + left_row.len()].copy_from_slice(left_row);
+            joined.pixels[start + left_row.len()..start + joined.width as usize]
+                .copy_from_slice(right_row);
+        }
+        joined
+    }
+
+    ///
+    /// Stacks this image on top of `bottom`. They must be the same width.
+    ///
+    pub fn above(&self, bottom: &Framebuffer) -> Framebuffer {
+        assert_eq!(
+            self.width, bottom.width,
+            "Stacked images need the same width"
+        );
+        let mut pixels = self.pixels.clone();
+        pixels.extend_from_slice(&bottom.pixels);
+        Framebuffer {
+            width: self.width,
+            height: self.height + bottom.height,
+            pixels,
+        }
+    }
+
+    fn row(&self, y: u32) -> &[Color] {
+        let start = self.index(0, y);
+        &self.pixels[start..start + self.width as usize]
+    }
+
+    ///
+    /// Converts to a gamma corrected 8-bit image, ready to be saved
+    ///
+    pub fn to_rgb8(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| Rgb::from(&self.pixel(x, y)))
+    }
+
+    ///
+    /// Converts to an 8-bit image, exposed, tone mapped and encoded by `tone_mapping`
+    ///
+    pub fn to_rgb8_with(&self, tone_mapping: &ToneMapping) -> RgbImage {
+        let mapped = tone_mapping.map_image(self);
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            quantize(mapped[self.index(x, y)])
+        })
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "Pixel ({x}, {y}) is outside of the {}x{} image",
+            self.width,
+            self.height
+        );
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+///
+/// Weighted sums of the samples around every pixel, which become a `Framebuffer` once every
+/// sample is in
+///
+pub(crate) struct WeightedFramebuffer {
+    width: u32,
+    height: u32,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+}
+
+impl WeightedFramebuffer {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        let len = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            sums: vec![Color::black(); len],
+            weights: vec![0.0; len],
+        }
+    }
+
+    ///
+    /// Adds a sample at (x, y) in pixels from the top left corner of the image to every pixel
+    /// whose center is within `radius` on both axes, weighted by `filter`. `radius` must be at
+    /// least 0.5.
+    ///
+    pub(crate) fn splat(&mut self, x: f64, y: f64, color: Color, filter: Filter, radius: f64) {
+        // Pixel centers are at half pixels. A radius of at least half a pixel always reaches one.
+        let (x, y) = (x - 0.5, y - 0.5);
+        let columns =
+            (x - radius).ceil().max(0.0) as u32..=((x + radius).floor() as u32).min(self.width - 1);
+        let rows = (y - radius).ceil().max(0.0) as u32
+            ..=((y + radius).floor() as u32).min(self.height - 1);
+
+        for py in rows {
+            let wy = filter.weight(py as f64 - y, radius);
+            if wy == 0.0 {
+                continue;
+            }
+            for px in columns.clone() {
+                let weight = wy * filter.weight(px as f64 - x, radius);
+                let index = py as usize * self.width as usize + px as usize;
+                self.sums[index] += color * weight;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    ///
+    /// Divides every pixel's sum by its total weight. Pixels that no sample reached are black.
+    ///
+    pub(crate) fn resolve(&self) -> Framebuffer {
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .sums
+                .iter()
+                .zip(&self.weights)
+                .map(|(sum, weight)| {
+                    if *weight == 0.0 {
+                        Color::black()
+                    } else {
+                        *sum / *weight
+                    }
+                })
+                .collect(),
+        }
+    }
+}
@@ -76,6 +76,9 @@ impl Aabb {
         let ray_orig = r.origin();
         let ray_dir = r.direction();
 
+        // Narrowed by each axis in turn, the box is hit if anything is left
+        let mut ray_min = *ray_t.start();
+        let mut ray_max = *ray_t.end();
         for axis in 0..3 {
             let ax = self.index(axis);
             let adinv = 1.0 / ray_dir[axis];
@@ -83,8 +86,6 @@ impl Aabb {
             let t0 = (ax.start() - ray_orig[axis]) * adinv;
             let t1 = (ax.end() - ray_orig[axis]) * adinv;
 
-            let mut ray_min = *ray_t.start();
-            let mut ray_max = *ray_t.end();
             if t0 < t1 {
                 if t0 > ray_min {
                     ray_min = t0;
@@ -109,6 +110,25 @@ impl Aabb {
         true
     }
 
+    ///
+    /// Total area of the box's six faces, 0 for an empty box
+    ///
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
+        }
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    ///
+    /// Middle of the box along `axis`
+    ///
+    pub fn center(&self, axis: usize) -> f64 {
+        let interval = self.index(axis);
+        0.5 * (interval.start() + interval.end())
+    }
+
     pub fn longest_axis(&self) -> i32 {
         if self.x.size() > self.y.size() {
             if self.x.size() > self.z.size() {
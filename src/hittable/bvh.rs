@@ -1,84 +1,586 @@
 use std::cmp::Ordering;
 
-use super::{aabb::Aabb, hittable_list::HittableList, Hittable};
+use rand::Rng;
+use serde::{de::IntoDeserializer, Deserialize};
 
+use crate::{
+    interval::Interval,
+    ray::Ray,
+    rng,
+    vec3::{Point3, Vec3},
+};
+
+use super::{aabb::Aabb, hittable_list::HittableList, HitRecord, Hittable};
+
+///
+/// Bounding volume hierarchy, stored as a flat list of nodes in depth first order so that a
+/// node's first child directly follows it. Leaves refer to a run of `objects`.
+///
 #[derive(Clone)]
 pub struct BvhNode {
-    pub(super) left: Box<Hittable>,
-    pub(super) right: Box<Hittable>,
-    pub(super) bbox: Aabb,
+    nodes: Vec<Node>,
+    objects: Vec<Hittable>,
+}
+
+#[derive(Clone)]
+struct Node {
+    bbox: Aabb,
+    kind: NodeKind,
+}
+
+#[derive(Clone, Copy)]
+enum NodeKind {
+    /// The first child is the next node, `second` is the index of the other. `axis` is the axis
+    /// the objects were divided along.
+    Interior { second: usize, axis: usize },
+
+    /// Holds `objects[first..first + count]`
+    Leaf { first: usize, count: usize },
+}
+
+///
+/// Shape of a `BvhNode`'s tree, see `BvhNode::stats`, or of several trees together
+///
+#[derive(Clone, Copy, Debug)]
+pub struct BvhStats {
+    pub trees: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    /// Number of nodes on the longest path from the root to a leaf, of the deepest tree
+    pub depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub mean_leaf_size: f64,
+}
+
+impl BvhStats {
+    ///
+    /// Stats of this tree and `other` taken together
+    ///
+    pub fn merge(self, other: BvhStats) -> BvhStats {
+        let leaves = self.leaves + other.leaves;
+        let objects =
+            self.mean_leaf_size * self.leaves as f64 + other.mean_leaf_size * other.leaves as f64;
+        BvhStats {
+            trees: self.trees + other.trees,
+            nodes: self.nodes + other.nodes,
+            leaves,
+            depth: self.depth.max(other.depth),
+            min_leaf_size: self.min_leaf_size.min(other.min_leaf_size),
+            max_leaf_size: self.max_leaf_size.max(other.max_leaf_size),
+            mean_leaf_size: objects / leaves as f64,
+        }
+    }
+}
+
+impl std::fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}, {} nodes, {} leaves, depth {}, objects per leaf {}-{} (mean {:.2})",
+            self.trees,
+            if self.trees == 1 { "tree" } else { "trees" },
+            self.nodes,
+            self.leaves,
+            self.depth,
+            self.min_leaf_size,
+            self.max_leaf_size,
+            self.mean_leaf_size
+        )
+    }
+}
+
+///
+/// How a `BvhNode` divides its objects between its two children
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BvhSplit {
+    /// Sort by box minimum along the longest axis, and split at the median
+    #[default]
+    Median,
+
+    ///
+    /// Bin objects by box center, and split where the surface area heuristic estimates the
+    /// cheapest tree
+    ///
+    Sah,
 }
 
+impl std::str::FromStr for BvhSplit {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+// Cost of testing a ray against a node's box, relative to testing it against an object
+const TRAVERSAL_COST: f64 = 1.0;
+
+// Number of buckets the SAH sorts objects into along each axis
+const SAH_BINS: usize = 16;
+
+// Most objects the SAH will put in a single leaf
+const MAX_LEAF_SIZE: usize = 4;
+
+// Below this depth nodes are split at the median, which halves the objects each level. This
+// bounds the depth of the tree, so traversal can use a fixed size stack.
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
 impl BvhNode {
     pub fn from_list(list: HittableList) -> Self {
         BvhNode::new(list.objects)
     }
 
-    pub fn new(mut objects: Vec<Hittable>) -> Self {
-        let mut bbox = Aabb::empty();
-        for o in objects.iter() {
-            bbox = Aabb::from_boxes(&bbox, &o.bounding_box());
-        }
+    ///
+    /// Builds a tree over `objects`, split at the median. See `with_split` for the SAH.
+    ///
+    pub fn new(objects: Vec<Hittable>) -> Self {
+        BvhNode::with_split(objects, BvhSplit::Median)
+    }
 
-        let axis = bbox.longest_axis();
+    pub fn with_split(objects: Vec<Hittable>, split: BvhSplit) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * objects.len()),
+            objects: Vec::with_capacity(objects.len()),
+        };
+        let entries = objects
+            .into_iter()
+            .map(|object| {
+                let bbox = object.bounding_box();
+                let cost = object.cost();
+                Entry { object, bbox, cost }
+            })
+            .collect();
+        bvh.build(entries, split, 0);
+        bvh
+    }
+
+    pub(super) fn objects(&self) -> &[Hittable] {
+        &self.objects
+    }
+
+    pub(super) fn into_objects(self) -> Vec<Hittable> {
+        self.objects
+    }
+
+    pub(super) fn bbox(&self) -> &Aabb {
+        &self.nodes[0].bbox
+    }
 
-        let comparator = match axis {
-            0 => box_x_compare,
-            1 => box_y_compare,
-            2 => box_z_compare,
-            _ => unreachable!(),
+    ///
+    /// Expected cost of testing a ray against this tree, as estimated by the surface area
+    /// heuristic: the chance of a ray that hits a node also hitting a child is the ratio of their
+    /// surface areas. Lower is better, and a single object costs 1.
+    ///
+    pub fn cost(&self) -> f64 {
+        self.node_cost(0)
+    }
+
+    fn node_cost(&self, index: usize) -> f64 {
+        let node = &self.nodes[index];
+        match node.kind {
+            NodeKind::Leaf { first, count } => {
+                let objects = &self.objects[first..first + count];
+                TRAVERSAL_COST + objects.iter().map(Hittable::cost).sum::<f64>()
+            }
+            NodeKind::Interior { second, .. } => {
+                let area = node.bbox.surface_area();
+                let child_cost = |child: usize| {
+                    let ratio = if area > 0.0 {
+                        self.nodes[child].bbox.surface_area() / area
+                    } else {
+                        1.0
+                    };
+                    ratio * self.node_cost(child)
+                };
+                TRAVERSAL_COST + child_cost(index + 1) + child_cost(second)
+            }
+        }
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            trees: 1,
+            nodes: self.nodes.len(),
+            leaves: 0,
+            depth: 0,
+            min_leaf_size: usize::MAX,
+            max_leaf_size: 0,
+            mean_leaf_size: 0.0,
         };
 
-        let object_span = objects.len();
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            stats.depth = stats.depth.max(depth);
+            match self.nodes[index].kind {
+                NodeKind::Leaf { count, .. } => {
+                    stats.leaves += 1;
+                    stats.min_leaf_size = stats.min_leaf_size.min(count);
+                    stats.max_leaf_size = stats.max_leaf_size.max(count);
+                }
+                NodeKind::Interior { second, .. } => {
+                    stack.push((index + 1, depth + 1));
+                    stack.push((second, depth + 1));
+                }
+            }
+        }
+        stats.mean_leaf_size = self.objects.len() as f64 / stats.leaves as f64;
+
+        stats
+    }
+
+    ///
+    /// Finds the closest hit in `ray_t`. Of a node's two children, the one nearer the ray's
+    /// origin along the axis they were divided on is visited first, so that later boxes can be
+    /// culled against the closest hit found so far.
+    ///
+    pub(super) fn hit(&self, r: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let t_min = *ray_t.start();
+        let mut closest = *ray_t.end();
+        let mut rec = None;
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if node.bbox.hit(r, t_min..=closest) {
+                match node.kind {
+                    NodeKind::Leaf { first, count } => {
+                        for object in &self.objects[first..first + count] {
+                            if let Some(hit) = object.hit(r, t_min..=closest) {
+                                closest = hit.t;
+                                rec = Some(hit);
+                            }
+                        }
+                    }
+                    NodeKind::Interior { second, axis } => {
+                        let (near, far) = if r.direction()[axis] < 0.0 {
+                            (second, index + 1)
+                        } else {
+                            (index + 1, second)
+                        };
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        index = near;
+                        continue;
+                    }
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            index = stack[stack_len];
+        }
 
-        let left;
-        let right;
-        if object_span == 1 {
-            left = objects[0].clone();
-            right = objects[0].clone();
-        } else if object_span == 2 {
-            left = objects[0].clone();
-            right = objects[1].clone();
+        rec
+    }
+
+    ///
+    /// Density of `r`'s direction when sampled by `random`, which picks every object with equal
+    /// probability. The ray can reach several objects, but only those in the boxes it passes
+    /// through, so the rest of the tree is skipped.
+    ///
+    pub(super) fn pdf_value(&self, r: Ray) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        // Wider than the holdoff of the objects' own densities, so no box they count is missed
+        let ray_t = 0.0..=f64::INFINITY;
+        let mut sum = 0.0;
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if node.bbox.hit(r, ray_t.clone()) {
+                match node.kind {
+                    NodeKind::Leaf { first, count } => {
+                        let objects = &self.objects[first..first + count];
+                        sum += objects.iter().map(|o| o.pdf_value(r)).sum::<f64>();
+                    }
+                    NodeKind::Interior { second, .. } => {
+                        stack[stack_len] = second;
+                        stack_len += 1;
+                        index += 1;
+                        continue;
+                    }
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            index = stack[stack_len];
+        }
+
+        sum / self.objects.len() as f64
+    }
+
+    ///
+    /// Returns a direction from `origin` towards a random point on a randomly chosen object
+    ///
+    pub(super) fn random(&self, origin: Point3, time: f64) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let index = rng::thread_rng().gen_range(0..self.objects.len());
+        self.objects[index].random(origin, time)
+    }
+
+    ///
+    /// Appends the node for `entries` and everything below it, returning its index
+    ///
+    fn build(&mut self, mut entries: Vec<Entry>, split: BvhSplit, depth: usize) -> usize {
+        let bbox = entries
+            .iter()
+            .fold(Aabb::empty(), |bbox, e| Aabb::from_boxes(&bbox, &e.bbox));
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bbox: bbox.clone(),
+            kind: NodeKind::Leaf { first: 0, count: 0 },
+        });
+
+        let divided = if entries.len() <= 1 {
+            None
+        } else if split == BvhSplit::Sah && depth < MAX_SAH_DEPTH {
+            match split_sah(&mut entries) {
+                Some((split_cost, axis, right)) => {
+                    // Keep small groups together when testing them all beats dividing them
+                    let leaf_cost: f64 = entries.iter().chain(&right).map(|e| e.cost).sum();
+                    let split_cost = split_cost / bbox.surface_area();
+                    if entries.len() + right.len() <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+                        entries.extend(right);
+                        None
+                    } else {
+                        Some((axis, right))
+                    }
+                }
+                None => Some(split_median(&mut entries)),
+            }
         } else {
-            objects.sort_unstable_by(comparator);
+            Some(split_median(&mut entries))
+        };
+
+        self.nodes[index].kind = match divided {
+            Some((axis, right)) => {
+                self.build(entries, split, depth + 1);
+                let second = self.build(right, split, depth + 1);
+                NodeKind::Interior { second, axis }
+            }
+            None => {
+                let first = self.objects.len();
+                let count = entries.len();
+                self.objects.extend(entries.into_iter().map(|e| e.object));
+                NodeKind::Leaf { first, count }
+            }
+        };
+
+        index
+    }
+}
+
+///
+/// An object to place in the tree, with its bounding box and cost computed once
+///
+struct Entry {
+    object: Hittable,
+    bbox: Aabb,
+    cost: f64,
+}
+
+///
+/// Sorts by box minimum on the longest axis, and splits off the upper half. Returns the axis
+/// along with the upper half.
+///
+fn split_median(objects: &mut Vec<Entry>) -> (usize, Vec<Entry>) {
+    let bbox = objects
+        .iter()
+        .fold(Aabb::empty(), |bbox, o| Aabb::from_boxes(&bbox, &o.bbox));
+    let axis = bbox.longest_axis() as usize;
+
+    objects.sort_unstable_by(|a, b| box_compare(&a.bbox, &b.bbox, axis));
+
+    let mid = objects.len() / 2;
+    (axis, objects.split_off(mid))
+}
 
-            let mid = object_span / 2;
+///
+/// Bins objects by box center along each axis, and splits off the objects past the cheapest
+/// bin boundary. Returns the split's cost scaled by the parent's surface area, and the axis,
+/// along with the objects split off. Returns None when the centers can't be told apart.
+///
+fn split_sah(objects: &mut Vec<Entry>) -> Option<(f64, usize, Vec<Entry>)> {
+    let centers = objects.iter().fold(Aabb::empty(), |bbox, o| {
+        let center = Point3::new(o.bbox.center(0), o.bbox.center(1), o.bbox.center(2));
+        Aabb::from_boxes(&bbox, &Aabb::from_points(center, center))
+    });
 
-            let right_list = objects.split_off(mid);
-            let left_list = objects;
+    // Best (cost, axis, number of bins on the left) found so far
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        let extent = centers.index(axis);
+        let (min, size) = (*extent.start(), extent.end() - extent.start());
+        if size <= 0.0 {
+            continue;
+        }
+        let bin_of = |b: &Aabb| sah_bin(b, axis, min, size);
 
-            left = BvhNode::new(left_list).into();
-            right = BvhNode::new(right_list).into();
+        // Bounds, object count and summed object cost of each bin
+        let mut bins: Vec<(Aabb, usize, f64)> = vec![(Aabb::empty(), 0, 0.0); SAH_BINS];
+        for o in objects.iter() {
+            let bin = &mut bins[bin_of(&o.bbox)];
+            bin.0 = Aabb::from_boxes(&bin.0, &o.bbox);
+            bin.1 += 1;
+            bin.2 += o.cost;
         }
 
-        let bbox = Aabb::from_boxes(&left.bounding_box(), &right.bounding_box());
+        // Area, count and cost of everything right of each bin boundary, swept from the right
+        let mut right_side = vec![(0.0, 0, 0.0); SAH_BINS];
+        let (mut bbox, mut count, mut cost) = (Aabb::empty(), 0, 0.0);
+        for i in (1..SAH_BINS).rev() {
+            bbox = Aabb::from_boxes(&bbox, &bins[i].0);
+            count += bins[i].1;
+            cost += bins[i].2;
+            right_side[i] = (bbox.surface_area(), count, cost);
+        }
 
-        Self {
-            left: Box::new(left),
-            right: Box::new(right),
-            bbox,
+        let (mut bbox, mut count, mut cost) = (Aabb::empty(), 0, 0.0);
+        for i in 1..SAH_BINS {
+            bbox = Aabb::from_boxes(&bbox, &bins[i - 1].0);
+            count += bins[i - 1].1;
+            cost += bins[i - 1].2;
+            let (right_area, right_count, right_cost) = right_side[i];
+            if count == 0 || right_count == 0 {
+                continue;
+            }
+
+            // The parent's area is the same for every candidate, so it is left out
+            let split_cost = bbox.surface_area() * (TRAVERSAL_COST + cost)
+                + right_area * (TRAVERSAL_COST + right_cost);
+            if best.is_none_or(|(best_cost, _, _)| split_cost < best_cost) {
+                best = Some((split_cost, axis, i));
+            }
         }
     }
+
+    let (split_cost, axis, left_bins) = best?;
+    let extent = centers.index(axis);
+    let (min, size) = (*extent.start(), extent.end() - extent.start());
+    let (left, right): (Vec<_>, Vec<_>) = objects
+        .drain(..)
+        .partition(|o| sah_bin(&o.bbox, axis, min, size) < left_bins);
+    *objects = left;
+    Some((split_cost, axis, right))
 }
 
-fn box_compare(a: &Hittable, b: &Hittable, axis_index: usize) -> Ordering {
-    let a_axis_interval = a.bounding_box().index(axis_index).clone();
-    let b_axis_interval = b.bounding_box().index(axis_index).clone();
+///
+/// Bin of a box whose center along `axis` lies in the range of `size` starting at `min`
+///
+fn sah_bin(b: &Aabb, axis: usize, min: f64, size: f64) -> usize {
+    let offset = (b.center(axis) - min) / size;
+    ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+}
 
-    a_axis_interval
+fn box_compare(a: &Aabb, b: &Aabb, axis_index: usize) -> Ordering {
+    a.index(axis_index)
         .start()
-        .partial_cmp(b_axis_interval.start())
+        .partial_cmp(b.index(axis_index).start())
         .unwrap_or(Ordering::Less)
 }
 
-fn box_x_compare(a: &Hittable, b: &Hittable) -> Ordering {
-    box_compare(a, b, 0)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color, hittable::sphere::Sphere, material::lambertian::Lambertian, rng::random,
+    };
 
-fn box_y_compare(a: &Hittable, b: &Hittable) -> Ordering {
-    box_compare(a, b, 1)
-}
+    fn sphere(center: Point3, radius: f64) -> Hittable {
+        Sphere::new(center, radius, Lambertian::new(Color::white())).into()
+    }
+
+    fn random_point(scale: f64) -> Point3 {
+        scale * (2.0 * Vec3::random() - Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    // Checks the hits of each split against testing every object in turn
+    fn assert_hits_match(objects: Vec<Hittable>, rays: usize) {
+        let mut list = HittableList::new();
+        objects.iter().for_each(|o| list.add(o.clone()));
+        let list: Hittable = list.into();
+        let trees = [BvhSplit::Median, BvhSplit::Sah]
+            .map(|split| BvhNode::with_split(objects.clone(), split));
 
-fn box_z_compare(a: &Hittable, b: &Hittable) -> Ordering {
-    box_compare(a, b, 2)
+        for _ in 0..rays {
+            let r = Ray::new(random_point(12.0), Vec3::random_unit_vector());
+            let expected = list.hit(r, 0.001..=f64::INFINITY);
+            for (tree, split) in trees.iter().zip(["median", "SAH"]) {
+                let found = tree.hit(r, 0.001..=f64::INFINITY);
+                match (&found, &expected) {
+                    (Some(a), Some(b)) => assert_eq!((a.t, a.p), (b.t, b.p), "{split}"),
+                    (None, None) => {}
+                    _ => panic!(
+                        "{split} tree hit {}, the list {}",
+                        found.is_some(),
+                        expected.is_some()
+                    ),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn splits_find_the_same_hits_as_a_list() {
+        rng::start_stream(&[8]);
+        let objects = (0..500)
+            .map(|_| sphere(random_point(10.0), 0.05 + 0.5 * random::<f64>()))
+            .collect();
+        assert_hits_match(objects, 5_000);
+    }
+
+    #[test]
+    fn density_matches_a_list() {
+        rng::start_stream(&[6]);
+        let objects: Vec<Hittable> = (0..200)
+            .map(|_| sphere(random_point(10.0), 0.1 + random::<f64>()))
+            .collect();
+        let mut list = HittableList::new();
+        objects.iter().for_each(|o| list.add(o.clone()));
+        let tree = BvhNode::with_split(objects, BvhSplit::Sah);
+
+        for _ in 0..5_000 {
+            let r = Ray::new(random_point(12.0), Vec3::random_unit_vector());
+            let (found, expected) = (tree.pdf_value(r), list.pdf_value(r));
+            assert!(
+                (found - expected).abs() <= 1e-12 * expected,
+                "{found} against {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn coincident_centers_keep_the_tree_shallow() {
+        rng::start_stream(&[8]);
+        // Nested spheres that the SAH can't tell apart, with a trail of ever further ones that it
+        // peels off one per level, more of them than the stack has room for
+        let mut objects: Vec<Hittable> = (0..2_000)
+            .map(|i| sphere(Point3::new(0.0, 0.0, 0.0), 0.1 + 0.001 * i as f64))
+            .collect();
+        objects.extend((1..64).map(|i| sphere(Point3::new(20f64.powi(i), 0.0, 0.0), 0.5)));
+
+        for split in [BvhSplit::Median, BvhSplit::Sah] {
+            let depth = BvhNode::with_split(objects.clone(), split).stats().depth;
+            assert!(depth <= STACK_SIZE, "{split:?}: {depth}");
+        }
+        assert_hits_match(objects, 2_000);
+    }
 }
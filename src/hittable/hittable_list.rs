@@ -1,3 +1,11 @@
+use rand::Rng;
+
+use crate::{
+    ray::Ray,
+    rng,
+    vec3::{Point3, Vec3},
+};
+
 use super::{aabb::Aabb, Hittable};
 
 #[derive(Clone)]
@@ -24,6 +32,46 @@ impl HittableList {
         self.bbox = Aabb::from_boxes(&self.bbox, &object.bounding_box());
         self.objects.push(object);
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    ///
+    /// Density of `r`'s direction when sampled by `random`, which picks every object with equal
+    /// probability
+    ///
+    pub fn pdf_value(&self, r: Ray) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(r))
+            .sum()
+    }
+
+    ///
+    /// Returns a direction from `origin` towards a random point on a randomly chosen object
+    ///
+    pub fn random(&self, origin: Point3, time: f64) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let index = rng::thread_rng().gen_range(0..self.objects.len());
+        self.objects[index].random(origin, time)
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FromIterator<Hittable> for HittableList {
@@ -1,9 +1,11 @@
-use std::{
-    f64::{INFINITY, NEG_INFINITY},
-    sync::Arc,
-};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
 
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    matrix::{Matrix4, Quaternion},
+    vec3::{Point3, Vec3},
+};
 
 use super::{aabb::Aabb, Hittable};
 
@@ -55,8 +57,8 @@ impl YRotate {
         let cos_theta = radians.cos();
         let bbox: Aabb = object.bounding_box();
 
-        let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
-        let mut max = Point3::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
 
         for i in 0..2 {
             for j in 0..2 {
@@ -86,7 +88,297 @@ impl YRotate {
             object,
             sin_theta,
             cos_theta,
+            bbox: Aabb::from_points(min, max),
+        }
+    }
+}
+
+impl YRotate {
+    ///
+    /// Rotates a world space vector into object space
+    ///
+    pub(super) fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() - self.sin_theta * v.z(),
+            v.y(),
+            self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+
+    ///
+    /// Rotates an object space vector into world space
+    ///
+    pub(super) fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() + self.sin_theta * v.z(),
+            v.y(),
+            -self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+}
+
+///
+/// An object placed by an arbitrary affine transform, such as a rotation about any axis, an
+/// uneven scale, or a matrix from another program
+///
+#[derive(Clone)]
+pub struct Transform {
+    pub(super) object: Arc<Hittable>,
+    pub(super) to_world: Matrix4,
+    pub(super) to_object: Matrix4,
+    pub(super) bbox: Aabb,
+}
+
+impl Transform {
+    ///
+    /// Fails if `transform` is projective, or can't be inverted, such as a scale by 0
+    ///
+    pub fn new(object: impl Into<Hittable>, transform: Matrix4) -> Result<Self> {
+        Self::from_arc(Arc::new(object.into()), transform)
+    }
+
+    pub fn from_arc(object: Arc<Hittable>, transform: Matrix4) -> Result<Self> {
+        if !transform.is_affine() {
+            bail!(
+                "the last row of the matrix must be [0, 0, 0, 1], not {:?}",
+                transform.last_row()
+            );
+        }
+        let Some(to_object) = transform.inverse() else {
+            bail!("the matrix is singular, it cannot be inverted");
+        };
+        let bbox = transformed_bbox(&object.bounding_box(), &transform, 0.0);
+
+        Ok(Self {
+            object,
+            to_world: transform,
+            to_object,
             bbox,
+        })
+    }
+}
+
+///
+/// World space box containing every transformed corner of `bbox`, grown by `padding` on all sides
+///
+fn transformed_bbox(bbox: &Aabb, transform: &Matrix4, padding: f64) -> Aabb {
+    let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for corner in corners(bbox) {
+        let corner = transform.transform_point(corner);
+        for c in 0..3 {
+            min[c] = f64::min(min[c], corner[c] - padding);
+            max[c] = f64::max(max[c], corner[c] + padding);
         }
     }
+    Aabb::from_points(min, max)
+}
+
+fn corners(bbox: &Aabb) -> impl Iterator<Item = Point3> + '_ {
+    (0..8).map(move |i: usize| {
+        let pick = |axis: usize| {
+            let interval = bbox.index(axis);
+            if i & (1 << axis) == 0 {
+                *interval.start()
+            } else {
+                *interval.end()
+            }
+        };
+        Point3::new(pick(0), pick(1), pick(2))
+    })
+}
+
+///
+/// The placement of a `KeyframedTransform` at one point in time: scaled, then rotated, then
+/// translated
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub scale: Vec3,
+    pub rotation: Quaternion,
+    pub translation: Vec3,
+}
+
+impl Keyframe {
+    ///
+    /// Leaves the object as it is at `time`
+    ///
+    pub fn new(time: f64) -> Self {
+        Self {
+            time,
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            rotation: Quaternion::identity(),
+            translation: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    ///
+    /// Rotates `angle` degrees around `axis`, after any rotation already set
+    ///
+    pub fn with_rotation(mut self, axis: Vec3, angle: f64) -> Self {
+        self.rotation = Quaternion::from_axis_angle(axis, angle) * self.rotation;
+        self
+    }
+
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    fn to_world(self) -> Matrix4 {
+        Matrix4::scaling(self.scale)
+            .then(self.rotation.to_matrix())
+            .then(Matrix4::translation(self.translation))
+    }
+
+    fn to_object(self) -> Matrix4 {
+        let inverse_scale = Vec3::new(
+            1.0 / self.scale.x(),
+            1.0 / self.scale.y(),
+            1.0 / self.scale.z(),
+        );
+        Matrix4::translation(-self.translation)
+            .then(self.rotation.to_matrix().transpose())
+            .then(Matrix4::scaling(inverse_scale))
+    }
+
+    ///
+    /// Blends towards `other` by `t` in 0..=1, interpolating scale and translation linearly and
+    /// rotation spherically
+    ///
+    fn lerp(&self, other: &Keyframe, t: f64) -> Keyframe {
+        Keyframe {
+            time: (1.0 - t) * self.time + t * other.time,
+            scale: (1.0 - t) * self.scale + t * other.scale,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            translation: (1.0 - t) * self.translation + t * other.translation,
+        }
+    }
+}
+
+///
+/// An object moving between keyframes over the ray time, for motion blur. Before the first
+/// keyframe and after the last it stays still. Rotations between neighboring keyframes take the
+/// shorter way around, so a spin needs a keyframe at least every half turn.
+///
+#[derive(Clone)]
+pub struct KeyframedTransform {
+    pub(super) object: Arc<Hittable>,
+    keyframes: Vec<Keyframe>,
+    pub(super) bbox: Aabb,
+}
+
+// Times per pair of keyframes at which the bounding box is sampled
+const MOTION_BOX_STEPS: usize = 16;
+
+impl KeyframedTransform {
+    ///
+    /// Fails if there are no keyframes, or a keyframe scales by 0
+    ///
+    pub fn new(object: impl Into<Hittable>, keyframes: Vec<Keyframe>) -> Result<Self> {
+        Self::from_arc(Arc::new(object.into()), keyframes)
+    }
+
+    pub fn from_arc(object: Arc<Hittable>, mut keyframes: Vec<Keyframe>) -> Result<Self> {
+        if keyframes.is_empty() {
+            bail!("a keyframed transform needs at least one keyframe");
+        }
+        if let Some(k) = keyframes
+            .iter()
+            .find(|k| k.scale.x() == 0.0 || k.scale.y() == 0.0 || k.scale.z() == 0.0)
+        {
+            bail!("the keyframe at time {} scales by 0", k.time);
+        }
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        // Union of the boxes at closely spaced times. Between them, rotating corners stray from
+        // the straight line joining their sampled positions by at most r (1 - cos(step / 2)), for
+        // a corner at distance r from the center of rotation.
+        let object_box = object.bounding_box();
+        let mut bbox = transformed_bbox(&object_box, &keyframes[0].to_world(), 0.0);
+        for pair in keyframes.windows(2) {
+            let step = pair[0].rotation.angle_to(&pair[1].rotation) / MOTION_BOX_STEPS as f64;
+            let radius = corners(&object_box)
+                .flat_map(|c| pair.iter().map(move |k| (c * k.scale).length()))
+                .fold(0.0, f64::max);
+            let padding = radius * (1.0 - (0.5 * step).cos());
+            for i in 0..=MOTION_BOX_STEPS {
+                let key = pair[0].lerp(&pair[1], i as f64 / MOTION_BOX_STEPS as f64);
+                bbox = Aabb::from_boxes(
+                    &bbox,
+                    &transformed_bbox(&object_box, &key.to_world(), padding),
+                );
+            }
+        }
+
+        Ok(Self {
+            object,
+            keyframes,
+            bbox,
+        })
+    }
+
+    ///
+    /// The object's transforms to and from world space at `time`
+    ///
+    pub(super) fn at(&self, time: f64) -> (Matrix4, Matrix4) {
+        let keys = &self.keyframes;
+        let next = keys.partition_point(|k| k.time <= time);
+        let key = if next == 0 {
+            keys[0]
+        } else if next == keys.len() {
+            keys[keys.len() - 1]
+        } else {
+            let (a, b) = (&keys[next - 1], &keys[next]);
+            a.lerp(b, (time - a.time) / (b.time - a.time))
+        };
+        (key.to_world(), key.to_object())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, hittable::sphere::Sphere, material::lambertian::Lambertian};
+
+    fn ball() -> Sphere {
+        Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Lambertian::new(Color::white()),
+        )
+    }
+
+    #[test]
+    fn transform_rejects_singular_and_projective_matrices() {
+        let flat = Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0));
+        assert!(Transform::new(ball(), flat).is_err());
+
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        rows[3] = [0.0, 0.5, 0.0, 1.0];
+        assert!(Transform::new(ball(), Matrix4::new(rows)).is_err());
+
+        assert!(Transform::new(ball(), Matrix4::rotation_x(30.0)).is_ok());
+    }
+
+    #[test]
+    fn keyframed_transform_rejects_missing_keyframes_and_zero_scale() {
+        assert!(KeyframedTransform::new(ball(), Vec::new()).is_err());
+
+        let flat = Keyframe::new(1.0).with_scale(Vec3::new(1.0, 1.0, 0.0));
+        let keyframes = vec![Keyframe::new(0.0), flat];
+        assert!(KeyframedTransform::new(ball(), keyframes).is_err());
+
+        let moving = Keyframe::new(1.0).with_translation(Vec3::new(1.0, 0.0, 0.0));
+        assert!(KeyframedTransform::new(ball(), vec![Keyframe::new(0.0), moving]).is_ok());
+    }
 }
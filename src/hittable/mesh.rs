@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    material::Material,
+    vec3::{Point3, Vec3},
+};
+
+use super::{bvh::BvhNode, hittable_list::HittableList, triangle::Triangle, Hittable};
+
+///
+/// One triangular face of a mesh. Each entry is an index into the matching buffer of the owning
+/// `MeshData`, so positions, normals and uvs can be shared between faces independently.
+///
+/// vertices: indices into the position buffer
+/// normals: indices into the normal buffer, or None to use the flat face normal
+/// uvs: indices into the uv buffer, or None to use the barycentric coordinates of the hit
+/// material: index into the material list
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Face {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+impl Face {
+    pub fn new(vertices: [usize; 3], material: usize) -> Self {
+        Self {
+            vertices,
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+}
+
+///
+/// Vertex and index buffers shared by every triangle of a mesh.
+///
+pub struct MeshData {
+    pub(super) positions: Vec<Point3>,
+    pub(super) normals: Vec<Vec3>,
+    pub(super) uvs: Vec<(f64, f64)>,
+    pub(super) faces: Vec<Face>,
+    pub(super) materials: Vec<Material>,
+}
+
+impl MeshData {
+    ///
+    /// Fails if any face refers to a vertex, normal, uv or material that does not exist.
+    ///
+    pub(super) fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<Face>,
+        materials: Vec<Material>,
+    ) -> Result<Self> {
+        for (i, face) in faces.iter().enumerate() {
+            let check = |indices: &[usize; 3], len: usize, kind: &str| {
+                if let Some(bad) = indices.iter().find(|&&idx| idx >= len) {
+                    bail!("Mesh face {i} uses {kind} index {bad}, but only {len} exist");
+                }
+                Ok(())
+            };
+
+            check(&face.vertices, positions.len(), "vertex")?;
+            if let Some(n) = &face.normals {
+                check(n, normals.len(), "normal")?;
+            }
+            if let Some(uv) = &face.uvs {
+                check(uv, uvs.len(), "uv")?;
+            }
+            if face.material >= materials.len() {
+                bail!(
+                    "Mesh face {i} uses material index {}, but only {} exist",
+                    face.material,
+                    materials.len()
+                );
+            }
+        }
+
+        Ok(Self {
+            positions,
+            normals,
+            uvs,
+            faces,
+            materials,
+        })
+    }
+
+    pub(super) fn face_vertices(&self, face: usize) -> [Point3; 3] {
+        let f = &self.faces[face];
+        [
+            self.positions[f.vertices[0]],
+            self.positions[f.vertices[1]],
+            self.positions[f.vertices[2]],
+        ]
+    }
+}
+
+#[derive(Clone)]
+pub struct Mesh {
+    pub(super) root: Box<Hittable>, // The triangles, each sharing the mesh's buffers
+}
+
+impl Mesh {
+    ///
+    /// Fails if any face refers to a vertex, normal, uv or material that does not exist.
+    ///
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<Face>,
+        materials: Vec<Material>,
+    ) -> Result<Self> {
+        let data = Arc::new(MeshData::new(positions, normals, uvs, faces, materials)?);
+
+        let triangles: Vec<Hittable> = (0..data.faces.len())
+            .map(|face| Triangle::from_mesh(data.clone(), face).into())
+            .collect();
+
+        let root = if triangles.is_empty() {
+            HittableList::new().into()
+        } else {
+            BvhNode::new(triangles).into()
+        };
+
+        Ok(Self {
+            root: Box::new(root),
+        })
+    }
+
+    ///
+    /// Builds a flat shaded mesh with a single material from a vertex buffer and a list of
+    /// triangles, each given as three indices into `positions`. Fails if an index is out of range.
+    ///
+    pub fn from_indexed(
+        positions: Vec<Point3>,
+        indices: Vec<[usize; 3]>,
+        mat: impl Into<Material>,
+    ) -> Result<Self> {
+        let faces = indices
+            .into_iter()
+            .map(|vertices| Face::new(vertices, 0))
+            .collect();
+        Self::new(positions, Vec::new(), Vec::new(), faces, vec![mat.into()])
+    }
+}
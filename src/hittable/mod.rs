@@ -3,17 +3,23 @@ pub mod bvh;
 pub mod constant_medium;
 pub mod hittable_list;
 pub mod instance;
+pub mod mesh;
 pub mod quad;
 pub mod sphere;
+pub mod triangle;
 
-use std::f64::INFINITY;
+use std::{f64::consts::PI, sync::Arc};
 
 use aabb::Aabb;
 
 use crate::{
     interval::{self, Interval},
     material::Material,
+    matrix::Matrix4,
+    onb::Onb,
+    pdf::random_to_sphere,
     ray::Ray,
+    rng::{self, random},
     vec3::{Point3, Vec3},
 };
 
@@ -52,11 +58,15 @@ impl HitRecord {
 pub enum Hittable {
     Sphere(sphere::Sphere),
     Quad(quad::Quad),
+    Triangle(triangle::Triangle),
+    Mesh(mesh::Mesh),
     ConstantMedium(constant_medium::ConstantMedium),
     HittableList(hittable_list::HittableList),
     BvhNode(bvh::BvhNode),
     Translate(instance::Translate),
     YRotate(instance::YRotate),
+    Transform(instance::Transform),
+    KeyframedTransform(instance::KeyframedTransform),
 }
 
 impl From<sphere::Sphere> for Hittable {
@@ -71,6 +81,18 @@ impl From<quad::Quad> for Hittable {
     }
 }
 
+impl From<triangle::Triangle> for Hittable {
+    fn from(value: triangle::Triangle) -> Self {
+        Hittable::Triangle(value)
+    }
+}
+
+impl From<mesh::Mesh> for Hittable {
+    fn from(value: mesh::Mesh) -> Self {
+        Hittable::Mesh(value)
+    }
+}
+
 impl From<constant_medium::ConstantMedium> for Hittable {
     fn from(value: constant_medium::ConstantMedium) -> Self {
         Hittable::ConstantMedium(value)
@@ -101,6 +123,18 @@ impl From<instance::YRotate> for Hittable {
     }
 }
 
+impl From<instance::Transform> for Hittable {
+    fn from(value: instance::Transform) -> Self {
+        Hittable::Transform(value)
+    }
+}
+
+impl From<instance::KeyframedTransform> for Hittable {
+    fn from(value: instance::KeyframedTransform) -> Self {
+        Hittable::KeyframedTransform(value)
+    }
+}
+
 impl Hittable {
     pub fn hit(&self, r: Ray, ray_t: Interval) -> Option<HitRecord> {
         use Hittable::*;
@@ -166,11 +200,7 @@ impl Hittable {
                 }
 
                 let p = r.at(t);
-                let uv = q.quad_uv(p);
-                if uv.is_none() {
-                    return None;
-                }
-                let (u, v) = uv.expect("This is always safe, we just handled the None case above");
+                let (u, v) = q.quad_uv(p)?;
 
                 let mat = q.mat.clone();
                 let (front_face, normal) = HitRecord::get_face_normal(r, q.normal);
@@ -186,27 +216,77 @@ impl Hittable {
                 })
             }
 
-            ConstantMedium(m) => {
-                // This hit function assumes the boundary of m is convex. It WILL NOT WORK if it isn't.
+            Triangle(tri) => {
+                // Moller-Trumbore intersection
+                let [a, b, c] = tri.vertices();
+                let edge1 = b - a;
+                let edge2 = c - a;
 
-                // Print occasional samples when debugging. To enable, set ENABLE_DEBUG true.
-                const ENABLE_DEBUG: bool = false;
-                let debugging = ENABLE_DEBUG && rand::random::<f64>() < 0.00001;
+                let pvec = r.direction().cross(edge2);
+                let det = edge1.dot(pvec);
 
-                // Get two hits on the boundary, to know min and max locations of hits
-                let rec1 = m.boundary.hit(r, interval::UNIVERSE);
-                if rec1.is_none() {
+                // No hit if the ray is parallel to the plane of the triangle.
+                const NEAR_ZERO_THRESHOLD: f64 = 1e-12;
+                if det.abs() < NEAR_ZERO_THRESHOLD {
+                    return None;
+                }
+                let inv_det = 1.0 / det;
+
+                let tvec = *r.origin() - a;
+                let b1 = tvec.dot(pvec) * inv_det;
+                if !(0.0..=1.0).contains(&b1) {
+                    return None;
+                }
+
+                let qvec = tvec.cross(edge1);
+                let b2 = r.direction().dot(qvec) * inv_det;
+                if b2 < 0.0 || b1 + b2 > 1.0 {
                     return None;
                 }
-                let mut rec1 =
-                    rec1.expect("This is safe, because we just handled the None case above");
 
-                let rec2 = m.boundary.hit(r, (rec1.t + 0.0001)..=INFINITY);
-                if rec2.is_none() {
+                let t = edge2.dot(qvec) * inv_det;
+                if !ray_t.contains(&t) {
                     return None;
                 }
-                let mut rec2 =
-                    rec2.expect("This is safe, because we just handled the None case above");
+
+                let p = r.at(t);
+                let outward_normal = edge1.cross(edge2).unit_vector();
+                let (front_face, geometric_normal) = HitRecord::get_face_normal(r, outward_normal);
+
+                // Shading normals are flipped onto the same side as the geometric normal, so that
+                // both always face the incoming ray.
+                let normal = match tri.shading_normal(b1, b2) {
+                    Some(n) if n.dot(geometric_normal) < 0.0 => -n,
+                    Some(n) => n,
+                    None => geometric_normal,
+                };
+                let (u, v) = tri.uv(b1, b2);
+                let mat = tri.material();
+
+                Some(HitRecord {
+                    p,
+                    normal,
+                    mat,
+                    t,
+                    u,
+                    v,
+                    front_face,
+                })
+            }
+
+            Mesh(m) => m.root.hit(r, ray_t),
+
+            ConstantMedium(m) => {
+                // This hit function assumes the boundary of m is convex. It WILL NOT WORK if it isn't.
+
+                // Print occasional samples when debugging. To enable, set ENABLE_DEBUG true.
+                const ENABLE_DEBUG: bool = false;
+                let debugging = ENABLE_DEBUG && random::<f64>() < 0.00001;
+
+                // Get two hits on the boundary, to know min and max locations of hits
+                let mut rec1 = m.boundary.hit(r, interval::UNIVERSE)?;
+                let mut rec2 = m.boundary.hit(r, (rec1.t + 0.0001)..=f64::INFINITY)?;
+                let entered = rec1.t;
 
                 if debugging {
                     println!("\nt_min={}, t_max={}", rec1.t, rec2.t);
@@ -217,7 +297,7 @@ impl Hittable {
                     rec1.t = *ray_t.start()
                 }
                 if rec2.t > *ray_t.end() {
-                    rec2.t = *ray_t.start()
+                    rec2.t = *ray_t.end()
                 }
 
                 // If the second hit is before the first hit, then we didn't hit the boundary
@@ -232,7 +312,14 @@ impl Hittable {
 
                 let ray_length = r.direction().length();
                 let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = m.neg_inv_density * rand::random::<f64>().ln();
+                // Keyed on the medium and where the ray enters it rather than drawn in turn, so
+                // that the distance doesn't depend on which media the search visited before. The
+                // entry is rounded so that transforms composed in another order give the same key.
+                let key = [
+                    m.neg_inv_density.to_bits(),
+                    (entered as f32).to_bits().into(),
+                ];
+                let hit_distance = m.neg_inv_density * rng::keyed_random(&key).ln();
 
                 if hit_distance > distance_inside_boundary {
                     return None;
@@ -272,25 +359,7 @@ impl Hittable {
                 temp_rec
             }
 
-            BvhNode(bvh) => {
-                if !bvh.bbox.hit(r, ray_t.clone()) {
-                    return None;
-                }
-
-                let mut temp_rec = bvh.left.hit(r, ray_t.clone());
-                let interval = if let Some(rec) = &temp_rec {
-                    *ray_t.start()..=rec.t
-                } else {
-                    ray_t
-                };
-                temp_rec = if let Some(rec) = bvh.right.hit(r, interval) {
-                    Some(rec)
-                } else {
-                    temp_rec
-                };
-
-                temp_rec
-            }
+            BvhNode(bvh) => bvh.hit(r, ray_t),
 
             Translate(t) => {
                 // Move the ray backwards by the offset
@@ -307,34 +376,23 @@ impl Hittable {
 
             YRotate(rot) => {
                 // Change from world space to object space
-                let mut origin = *r.origin();
-                let mut direction = *r.direction();
-
-                origin[0] = rot.cos_theta * r.origin()[0] - rot.sin_theta * r.origin()[2];
-                origin[2] = rot.sin_theta * r.origin()[0] + rot.cos_theta * r.origin()[2];
-
-                direction[0] = rot.cos_theta * r.direction()[0] - rot.sin_theta * r.direction()[2];
-                direction[2] = rot.sin_theta * r.direction()[0] + rot.cos_theta * r.direction()[2];
-
+                let origin = rot.to_object(*r.origin());
+                let direction = rot.to_object(*r.direction());
                 let rotated_r = Ray::with_time(origin, direction, r.time());
 
-                if let Some(mut rec) = rot.object.hit(rotated_r, ray_t) {
-                    // Change the intersection point from object space to world space
-                    let mut p = rec.p;
-                    p[0] = rot.cos_theta * rec.p[0] + rot.sin_theta * rec.p[2];
-                    p[2] = -rot.sin_theta * rec.p[0] + rot.cos_theta * rec.p[2];
-                    rec.p = p;
+                // Change the intersection point and normal from object space to world space
+                let mut rec = rot.object.hit(rotated_r, ray_t)?;
+                rec.p = rot.to_world(rec.p);
+                rec.normal = rot.to_world(rec.normal);
 
-                    // Change the normal from object space to world space
-                    let mut normal = rec.normal;
-                    normal[0] = rot.cos_theta * rec.normal[0] + rot.sin_theta * rec.normal[2];
-                    normal[2] = -rot.sin_theta * rec.normal[0] + rot.cos_theta * rec.normal[2];
-                    rec.normal = normal;
+                Some(rec)
+            }
 
-                    Some(rec)
-                } else {
-                    None
-                }
+            Transform(tr) => hit_transformed(&tr.object, &tr.to_world, &tr.to_object, r, ray_t),
+
+            KeyframedTransform(k) => {
+                let (to_world, to_object) = k.at(r.time());
+                hit_transformed(&k.object, &to_world, &to_object, r, ray_t)
             }
         }
     }
@@ -346,15 +404,327 @@ impl Hittable {
 
             Quad(q) => q.bbox.clone(),
 
+            Triangle(tri) => tri.bbox.clone(),
+
+            Mesh(m) => m.root.bounding_box(),
+
             ConstantMedium(m) => m.boundary.bounding_box(),
 
             HittableList(h) => h.bbox.clone(),
 
-            BvhNode(bvh) => bvh.bbox.clone(),
+            BvhNode(bvh) => bvh.bbox().clone(),
 
             Translate(t) => t.bbox.clone(),
 
             YRotate(y) => y.bbox.clone(),
+
+            Transform(tr) => tr.bbox.clone(),
+            KeyframedTransform(k) => k.bbox.clone(),
+        }
+    }
+
+    ///
+    /// Expected cost of testing a ray against this object, relative to testing a single
+    /// primitive, as estimated by the surface area heuristic. See `BvhNode::cost`.
+    ///
+    pub fn cost(&self) -> f64 {
+        use Hittable::*;
+        match self {
+            Sphere(_) | Quad(_) | Triangle(_) => 1.0,
+
+            Mesh(m) => m.root.cost(),
+
+            // Finding where a ray enters and leaves the boundary takes two tests
+            ConstantMedium(m) => 2.0 * m.boundary.cost(),
+
+            HittableList(h) => h.objects.iter().map(Hittable::cost).sum(),
+
+            BvhNode(bvh) => bvh.cost(),
+
+            Translate(t) => t.object.cost(),
+
+            YRotate(rot) => rot.object.cost(),
+
+            Transform(tr) => tr.object.cost(),
+            KeyframedTransform(k) => k.object.cost(),
+        }
+    }
+
+    ///
+    /// Shape of every BVH within this object taken together, including those nested in other
+    /// BVHs, instances and meshes, or None if there are none
+    ///
+    pub fn bvh_stats(&self) -> Option<bvh::BvhStats> {
+        let merge = |objects: &[Hittable]| {
+            objects
+                .iter()
+                .filter_map(Hittable::bvh_stats)
+                .reduce(bvh::BvhStats::merge)
+        };
+
+        use Hittable::*;
+        match self {
+            Sphere(_) | Quad(_) | Triangle(_) => None,
+
+            Mesh(m) => m.root.bvh_stats(),
+
+            ConstantMedium(m) => m.boundary.bvh_stats(),
+
+            HittableList(h) => merge(&h.objects),
+
+            BvhNode(bvh) => Some(match merge(bvh.objects()) {
+                Some(nested) => bvh.stats().merge(nested),
+                None => bvh.stats(),
+            }),
+
+            Translate(t) => t.object.bvh_stats(),
+
+            YRotate(rot) => rot.object.bvh_stats(),
+
+            Transform(tr) => tr.object.bvh_stats(),
+            KeyframedTransform(k) => k.object.bvh_stats(),
+        }
+    }
+
+    ///
+    /// Rebuilds every BVH within this object, including those of meshes, dividing their objects
+    /// with `split`. An object shared between several instances is copied for each of them.
+    ///
+    pub fn with_bvh_split(self, split: bvh::BvhSplit) -> Hittable {
+        let rebuild =
+            |object: Arc<Hittable>| Arc::new(Arc::unwrap_or_clone(object).with_bvh_split(split));
+
+        use Hittable::*;
+        match self {
+            Sphere(_) | Quad(_) | Triangle(_) => self,
+
+            Mesh(mut m) => {
+                m.root = Box::new(m.root.with_bvh_split(split));
+                m.into()
+            }
+
+            ConstantMedium(mut m) => {
+                m.boundary = Box::new(m.boundary.with_bvh_split(split));
+                m.into()
+            }
+
+            HittableList(mut h) => {
+                h.objects = h
+                    .objects
+                    .into_iter()
+                    .map(|object| object.with_bvh_split(split))
+                    .collect();
+                h.into()
+            }
+
+            BvhNode(bvh) => {
+                let objects = bvh
+                    .into_objects()
+                    .into_iter()
+                    .map(|object| object.with_bvh_split(split))
+                    .collect();
+                bvh::BvhNode::with_split(objects, split).into()
+            }
+
+            Translate(mut t) => {
+                t.object = rebuild(t.object);
+                t.into()
+            }
+
+            YRotate(mut rot) => {
+                rot.object = rebuild(rot.object);
+                rot.into()
+            }
+
+            Transform(mut tr) => {
+                tr.object = rebuild(tr.object);
+                tr.into()
+            }
+            KeyframedTransform(mut k) => {
+                k.object = rebuild(k.object);
+                k.into()
+            }
         }
     }
+
+    ///
+    /// Density, per unit solid angle, of `random` generating the direction of `r` from its
+    /// origin. Surfaces have one, while constant media are not meant to be in a list of lights.
+    ///
+    pub fn pdf_value(&self, r: Ray) -> f64 {
+        // Same holdoff as the camera uses, so that a light is never found at the ray origin
+        const SURFACE_HOLDOFF_DIST: f64 = 0.001;
+        let ray_t = SURFACE_HOLDOFF_DIST..=f64::INFINITY;
+
+        use Hittable::*;
+        match self {
+            Sphere(s) => {
+                let distance_squared = (s.sphere_center(r.time()) - *r.origin()).length_squared();
+                if distance_squared <= s.radius * s.radius {
+                    // Seen from inside, the sphere covers every direction
+                    return 1.0 / (4.0 * PI);
+                }
+                if self.hit(r, ray_t).is_none() {
+                    return 0.0;
+                }
+
+                let cos_theta_max = (1.0 - s.radius * s.radius / distance_squared).sqrt();
+                let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+                1.0 / solid_angle
+            }
+
+            Quad(q) => {
+                let Some(rec) = self.hit(r, ray_t) else {
+                    return 0.0;
+                };
+                let distance_squared = rec.t * rec.t * r.direction().length_squared();
+                let cosine = (r.direction().dot(rec.normal) / r.direction().length()).abs();
+                distance_squared / (cosine * q.area)
+            }
+
+            Triangle(tri) => {
+                let Some(rec) = self.hit(r, ray_t) else {
+                    return 0.0;
+                };
+                let [a, b, c] = tri.vertices();
+                let geometric_normal = (b - a).cross(c - a);
+                let area = 0.5 * geometric_normal.length();
+
+                let distance_squared = rec.t * rec.t * r.direction().length_squared();
+                let cosine = (r.direction().dot(geometric_normal)
+                    / (r.direction().length() * geometric_normal.length()))
+                .abs();
+                distance_squared / (cosine * area)
+            }
+
+            HittableList(h) => h.pdf_value(r),
+
+            Translate(t) => t.object.pdf_value(Ray::with_time(
+                *r.origin() - t.offset,
+                *r.direction(),
+                r.time(),
+            )),
+
+            YRotate(rot) => rot.object.pdf_value(Ray::with_time(
+                rot.to_object(*r.origin()),
+                rot.to_object(*r.direction()),
+                r.time(),
+            )),
+
+            Transform(tr) => pdf_transformed(&tr.object, &tr.to_object, r),
+
+            KeyframedTransform(k) => pdf_transformed(&k.object, &k.at(r.time()).1, r),
+
+            Mesh(m) => m.root.pdf_value(r),
+
+            BvhNode(b) => b.pdf_value(r),
+
+            ConstantMedium(_) => {
+                debug_assert!(false, "A constant medium cannot be sampled as a light");
+                0.0
+            }
+        }
+    }
+
+    ///
+    /// Returns a direction from `origin` towards a random point on this object, at `time`
+    ///
+    pub fn random(&self, origin: Point3, time: f64) -> Vec3 {
+        use Hittable::*;
+        match self {
+            Sphere(s) => {
+                let direction = s.sphere_center(time) - origin;
+                let distance_squared = direction.length_squared();
+                if distance_squared <= s.radius * s.radius {
+                    return Vec3::random_unit_vector();
+                }
+                let uvw = Onb::new(direction);
+                uvw.transform(random_to_sphere(s.radius, distance_squared))
+            }
+
+            Quad(q) => {
+                let p = q.q + (random::<f64>() * q.u) + (random::<f64>() * q.v);
+                p - origin
+            }
+
+            Triangle(tri) => {
+                // Uniform barycentric coordinates, from folding a random point in the unit square
+                let [a, b, c] = tri.vertices();
+                let (mut b1, mut b2) = (random::<f64>(), random::<f64>());
+                if b1 + b2 > 1.0 {
+                    (b1, b2) = (1.0 - b1, 1.0 - b2);
+                }
+                let p = a + b1 * (b - a) + b2 * (c - a);
+                p - origin
+            }
+
+            HittableList(h) => h.random(origin, time),
+
+            Translate(t) => t.object.random(origin - t.offset, time),
+
+            YRotate(rot) => rot.to_world(rot.object.random(rot.to_object(origin), time)),
+
+            Transform(tr) => {
+                random_transformed(&tr.object, &tr.to_world, &tr.to_object, origin, time)
+            }
+
+            KeyframedTransform(k) => {
+                let (to_world, to_object) = k.at(time);
+                random_transformed(&k.object, &to_world, &to_object, origin, time)
+            }
+
+            Mesh(m) => m.root.random(origin, time),
+
+            BvhNode(b) => b.random(origin, time),
+
+            ConstantMedium(_) => {
+                debug_assert!(false, "A constant medium cannot be sampled as a light");
+                Vec3::new(1.0, 0.0, 0.0)
+            }
+        }
+    }
+}
+
+///
+/// Hits an object placed by an affine transform. Because the transform is affine, t along the
+/// object space ray is the same as along the world space one.
+///
+fn hit_transformed(
+    object: &Hittable,
+    to_world: &Matrix4,
+    to_object: &Matrix4,
+    r: Ray,
+    ray_t: Interval,
+) -> Option<HitRecord> {
+    let origin = to_object.transform_point(*r.origin());
+    let direction = to_object.transform_vector(*r.direction());
+    let object_r = Ray::with_time(origin, direction, r.time());
+
+    // Normals go by the inverse transpose, to stay perpendicular under uneven scales
+    let mut rec = object.hit(object_r, ray_t)?;
+    rec.p = to_world.transform_point(rec.p);
+    rec.normal = to_object.transform_transposed(rec.normal).unit_vector();
+
+    Some(rec)
+}
+
+fn pdf_transformed(object: &Hittable, to_object: &Matrix4, r: Ray) -> f64 {
+    let origin = to_object.transform_point(*r.origin());
+    let direction = to_object.transform_vector(*r.direction());
+    let object_pdf = object.pdf_value(Ray::with_time(origin, direction, r.time()));
+
+    // Convert from density per object space solid angle to per world space solid angle. A
+    // linear map A scales the solid angle around a unit direction d by |det A| / |A d|^3.
+    let stretch = direction.length() / r.direction().length();
+    object_pdf * to_object.determinant3().abs() / stretch.powi(3)
+}
+
+fn random_transformed(
+    object: &Hittable,
+    to_world: &Matrix4,
+    to_object: &Matrix4,
+    origin: Point3,
+    time: f64,
+) -> Vec3 {
+    to_world.transform_vector(object.random(to_object.transform_point(origin), time))
 }
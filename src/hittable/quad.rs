@@ -16,6 +16,7 @@ pub struct Quad {
     pub(super) mat: Material,
     pub(super) normal: Vec3, // (A, B, B) constants for the plane containing the Quad
     pub(super) d: f64,       // remaining constant D for the plane containing the Quad
+    pub(super) area: f64,
 }
 
 impl Quad {
@@ -34,6 +35,7 @@ impl Quad {
             bbox: set_bounding_box(q, u, v),
             normal,
             d,
+            area: n.length(),
         }
     }
 
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use crate::{
+    material::Material,
+    vec3::{Point3, Vec3},
+};
+
+use super::{
+    aabb::Aabb,
+    mesh::{Face, MeshData},
+};
+
+///
+/// A single triangle. Triangles always reference their vertex data through a `MeshData`, so that
+/// the triangles of a `Mesh` can share one set of buffers. Standalone triangles own a mesh with a
+/// single face.
+///
+#[derive(Clone)]
+pub struct Triangle {
+    pub(super) mesh: Arc<MeshData>,
+    pub(super) face: usize,
+    pub(super) bbox: Aabb,
+}
+
+impl Triangle {
+    ///
+    /// Flat shaded triangle with vertices a, b and c. The front face is the side from which the
+    /// vertices appear counter-clockwise.
+    ///
+    pub fn new(a: Point3, b: Point3, c: Point3, mat: impl Into<Material>) -> Self {
+        let mesh = MeshData::new(
+            vec![a, b, c],
+            Vec::new(),
+            Vec::new(),
+            vec![Face::new([0, 1, 2], 0)],
+            vec![mat.into()],
+        )
+        .expect("A single face refers to buffers of three");
+        Self::from_mesh(Arc::new(mesh), 0)
+    }
+
+    ///
+    /// Smooth shaded triangle with a normal and texture coordinate at each vertex.
+    ///
+    pub fn with_attributes(
+        vertices: [Point3; 3],
+        normals: [Vec3; 3],
+        uvs: [(f64, f64); 3],
+        mat: impl Into<Material>,
+    ) -> Self {
+        let face = Face {
+            vertices: [0, 1, 2],
+            normals: Some([0, 1, 2]),
+            uvs: Some([0, 1, 2]),
+            material: 0,
+        };
+        let mesh = MeshData::new(
+            vertices.to_vec(),
+            normals.to_vec(),
+            uvs.to_vec(),
+            vec![face],
+            vec![mat.into()],
+        )
+        .expect("A single face refers to buffers of three");
+        Self::from_mesh(Arc::new(mesh), 0)
+    }
+
+    pub(super) fn from_mesh(mesh: Arc<MeshData>, face: usize) -> Self {
+        let [a, b, c] = mesh.face_vertices(face);
+        let bbox = Aabb::from_boxes(&Aabb::from_points(a, b), &Aabb::from_points(c, c));
+        Self { mesh, face, bbox }
+    }
+
+    pub(super) fn vertices(&self) -> [Point3; 3] {
+        self.mesh.face_vertices(self.face)
+    }
+
+    pub(super) fn material(&self) -> Material {
+        self.mesh.materials[self.mesh.faces[self.face].material].clone()
+    }
+
+    ///
+    /// Interpolates the per-vertex normals at barycentric coordinates (b1, b2), relative to the
+    /// second and third vertices. Returns None if the face has no vertex normals.
+    ///
+    pub(super) fn shading_normal(&self, b1: f64, b2: f64) -> Option<Vec3> {
+        let face = &self.mesh.faces[self.face];
+        let [n0, n1, n2] = face.normals?.map(|i| self.mesh.normals[i]);
+        let n = (1.0 - b1 - b2) * n0 + b1 * n1 + b2 * n2;
+        if n.near_zero() {
+            None
+        } else {
+            Some(n.unit_vector())
+        }
+    }
+
+    ///
+    /// Interpolates the per-vertex texture coordinates at barycentric coordinates (b1, b2). Faces
+    /// without texture coordinates use (b1, b2) directly, matching the parameterization of a Quad.
+    ///
+    pub(super) fn uv(&self, b1: f64, b2: f64) -> (f64, f64) {
+        let face = &self.mesh.faces[self.face];
+        match face.uvs {
+            Some(indices) => {
+                let [uv0, uv1, uv2] = indices.map(|i| self.mesh.uvs[i]);
+                let b0 = 1.0 - b1 - b2;
+                (
+                    b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                    b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+                )
+            }
+            None => (b1, b2),
+        }
+    }
+}
@@ -1,11 +1,9 @@
 use std::ops::RangeInclusive;
 
-use std::f64::{INFINITY, NEG_INFINITY};
-
 #[allow(dead_code)]
-pub const EMPTY: RangeInclusive<f64> = INFINITY..=NEG_INFINITY;
+pub const EMPTY: RangeInclusive<f64> = f64::INFINITY..=f64::NEG_INFINITY;
 #[allow(dead_code)]
-pub const UNIVERSE: RangeInclusive<f64> = NEG_INFINITY..=INFINITY;
+pub const UNIVERSE: RangeInclusive<f64> = f64::NEG_INFINITY..=f64::INFINITY;
 
 pub type Interval = RangeInclusive<f64>;
 
@@ -0,0 +1,47 @@
+//!
+//! A software raytracer based on the Ray Tracing in One Weekend series.
+//!
+//! Build a world out of `Hittable`s, either by hand, from one of the built-in `scene`s, or by
+//! loading a `scene_file`, then render it with a `Camera` into a `Framebuffer`. Emissive objects
+//! that are also passed as lights are sampled directly, which converges much faster:
+//!
+//! ```no_run
+//! use raytracer_v2::{scene, Camera};
+//!
+//! let world = scene::cornell_box();
+//! let lights = scene::cornell_box_lights();
+//! let image = scene::cornell_box_cam().render(&world, &lights);
+//! image.to_rgb8().save("cornell_box.png").unwrap();
+//! ```
+//!
+
+pub mod animation;
+pub mod aperture;
+pub mod camera;
+pub mod color;
+pub mod filter;
+pub mod framebuffer;
+pub mod hittable;
+pub mod interval;
+pub mod material;
+pub mod matrix;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod ray;
+pub mod rng;
+pub mod scene;
+pub mod scene_file;
+pub mod spectrum;
+pub mod texture;
+pub mod tonemap;
+pub mod vec3;
+
+pub use camera::Camera;
+pub use color::Color;
+pub use framebuffer::Framebuffer;
+pub use hittable::Hittable;
+pub use material::Material;
+pub use texture::Texture;
+pub use vec3::{Point3, Vec3};
@@ -1,20 +1,110 @@
-use anyhow::Result;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use scene::*;
+use anyhow::{Context, Result};
+use clap::Parser;
+use raytracer_v2::{
+    animation::Animation,
+    camera::{Camera, Eye},
+    output, Framebuffer,
+};
 
-mod camera;
-mod color;
-mod hittable;
-mod interval;
-mod material;
-mod ray;
-mod scene;
-mod texture;
-mod vec3;
+use cli::{Cli, RenderJob};
+
+mod cli;
 
 fn main() -> Result<()> {
-    let world = book2_final();
-    let cam = book2_final_camera(400, 250, 4);
+    let Some(mut job) = Cli::parse().into_job()? else {
+        return Ok(());
+    };
+
+    let Some(animation) = job.animation.take() else {
+        return render(&job, &job.output);
+    };
+
+    fs::create_dir_all(&job.output)
+        .with_context(|| format!("Cannot create {}", job.output.display()))?;
+    for frame in animation.frames {
+        let path = job
+            .output
+            .join(Animation::file_name(frame, job.format.extension()));
+        if animation.skip_existing && output_paths(&job, &path).iter().all(|p| p.exists()) {
+            println!("Frame {frame} exists, skipping");
+            continue;
+        }
+
+        println!("Frame {frame} of {}", animation.animation.frames);
+        (job.camera.shutter_open, job.camera.shutter_close) = animation.animation.shutter(frame);
+        render(&job, &path)?;
+    }
+
+    Ok(())
+}
+
+///
+/// Renders `job` to `path`, or for a stereo pair to the paths of its layout
+///
+fn render(job: &RenderJob, path: &Path) -> Result<()> {
+    let Some(layout) = job.stereo else {
+        let image = render_image(&job.camera, job)?;
+        return save(job, &image, path);
+    };
+
+    let left = render_image(
+        &Camera {
+            eye: Eye::Left,
+            ..job.camera.clone()
+        },
+        job,
+    )?;
+    let right = render_image(
+        &Camera {
+            eye: Eye::Right,
+            ..job.camera.clone()
+        },
+        job,
+    )?;
+    for (image, path) in layout.arrange(left, right).iter().zip(layout.paths(path)) {
+        save(job, image, &path)?;
+    }
+    Ok(())
+}
+
+fn output_paths(job: &RenderJob, path: &Path) -> Vec<PathBuf> {
+    match job.stereo {
+        Some(layout) => layout.paths(path),
+        None => vec![path.to_path_buf()],
+    }
+}
+
+fn render_image(camera: &Camera, job: &RenderJob) -> Result<Framebuffer> {
+    let mut stdout = std::io::stdout().lock();
+    let image = camera.render_with_progress(&job.world, &job.lights, |done, height| {
+        // Progress is best effort, a closed stdout shouldn't stop the render
+        let _ = write!(
+            stdout,
+            "\rScanlines remaining: {}                         ",
+            height - done
+        );
+        let _ = stdout.flush();
+    });
+    writeln!(
+        stdout,
+        "\rDone.                                                 "
+    )?;
+    Ok(image)
+}
 
-    cam.render(&world)
+///
+/// Writes to a temporary file first, so that an interrupted render never leaves a partial image
+/// behind for --skip-existing to mistake for a finished one
+///
+fn save(job: &RenderJob, image: &Framebuffer, path: &Path) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    output::save(image, &partial, job.format, &job.tone_mapping)?;
+    fs::rename(&partial, path).with_context(|| format!("Cannot write {}", path.display()))
 }
@@ -0,0 +1,198 @@
+use std::str::FromStr;
+
+use serde::{de::IntoDeserializer, Deserialize};
+
+use crate::{color::Color, hittable::HitRecord, ray::Ray};
+
+use super::{microfacet::Ggx, thin_film::ThinFilm};
+
+///
+/// A metal with microfacet roughness, which reflects light tinted by its complex refractive
+/// index. Unlike `Metal` it conserves energy, and its color shifts towards white at grazing
+/// angles.
+///
+#[derive(Clone)]
+pub struct Conductor {
+    pub(super) eta: Color, // Real part of the refractive index, per channel
+    pub(super) k: Color,   // Extinction coefficient, the imaginary part, per channel
+    pub(super) distribution: Ggx,
+    pub(super) film: Option<ThinFilm>, // Coating that colors reflections by interference, like an oxide layer
+}
+
+///
+/// Measured refractive indices of common metals
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl ConductorPreset {
+    ///
+    /// Real part and extinction coefficient of the refractive index, at wavelengths of about
+    /// 650, 550 and 450 nm for red, green and blue
+    ///
+    pub fn ior(self) -> (Color, Color) {
+        match self {
+            ConductorPreset::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.386, 1.603),
+            ),
+            ConductorPreset::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            ConductorPreset::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            ConductorPreset::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+impl FromStr for ConductorPreset {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+impl Conductor {
+    ///
+    /// `roughness` is in 0..=1, from a mirror to a very dull surface
+    ///
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self::anisotropic(eta, k, roughness, roughness)
+    }
+
+    ///
+    /// A surface rough by `roughness_u` along the tangent that circles the Y axis, and by
+    /// `roughness_v` across it, like metal brushed around Y
+    ///
+    pub fn anisotropic(eta: Color, k: Color, roughness_u: f64, roughness_v: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: Ggx::from_roughness(
+                roughness_u.clamp(0.0, 1.0),
+                roughness_v.clamp(0.0, 1.0),
+            ),
+            film: None,
+        }
+    }
+
+    ///
+    /// Coats the metal with `film`, like the oxide of anodized or heat-tinted metal
+    ///
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    pub fn preset(preset: ConductorPreset, roughness: f64) -> Self {
+        let (eta, k) = preset.ior();
+        Self::new(eta, k, roughness)
+    }
+
+    ///
+    /// Fresnel reflectance for light arriving along `r_in` at `cos_theta` to a microfacet's
+    /// normal at `rec`, through the film if there is one, at the wavelengths `r_in` carries in
+    /// spectral mode
+    ///
+    pub(super) fn fresnel(&self, r_in: Ray, cos_theta: f64, rec: &HitRecord) -> Color {
+        if let Some(film) = &self.film {
+            return film.reflectance(r_in, rec, cos_theta, |lambda| {
+                let (eta, k) = self.ior_at(lambda);
+                (1.0, eta, k)
+            });
+        }
+        r_in.uplift(Color::new(
+            fresnel_conductor(cos_theta, self.eta.r(), self.k.r()),
+            fresnel_conductor(cos_theta, self.eta.g(), self.k.g()),
+            fresnel_conductor(cos_theta, self.eta.b(), self.k.b()),
+        ))
+    }
+
+    ///
+    /// Refractive index at `lambda` nanometers, interpolated between the channels at 650, 550
+    /// and 450 nm
+    ///
+    fn ior_at(&self, lambda: f64) -> (f64, f64) {
+        let channels = |c: Color| {
+            let (r, g, b) = (c.r(), c.g(), c.b());
+            if lambda >= 550.0 {
+                let t = ((lambda - 550.0) / 100.0).min(1.0);
+                g + t * (r - g)
+            } else {
+                let t = ((550.0 - lambda) / 100.0).min(1.0);
+                g + t * (b - g)
+            }
+        };
+        (channels(self.eta), channels(self.k))
+    }
+}
+
+///
+/// Exact Fresnel reflectance of unpolarized light off a conductor with refractive index
+/// `eta + i k`
+///
+fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rs + rp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+        for (eta, k) in [(0.2, 3.9), (1.5, 0.0), (2.9, 3.0), (0.05, 4.2)] {
+            let expected: f64 =
+                ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+            let reflectance = fresnel_conductor(1.0, eta, k);
+            assert!(
+                (reflectance - expected).abs() < 1e-12,
+                "{eta} + {k}i: {reflectance} against {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn fresnel_reflects_everything_at_grazing_angles() {
+        let presets = [
+            ConductorPreset::Gold,
+            ConductorPreset::Copper,
+            ConductorPreset::Aluminium,
+            ConductorPreset::Silver,
+        ];
+        for (eta, k) in presets.map(|p| p.ior()) {
+            assert!((fresnel_conductor(0.0, eta.g(), k.g()) - 1.0).abs() < 1e-12);
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::vec3::Vec3;
+use crate::{color::Color, hittable::HitRecord, ray::Ray, vec3::Vec3};
+
+use super::{microfacet::Ggx, thin_film::ThinFilm};
 
 ///
 /// uv: unit vector of the incoming ray direction
@@ -21,6 +23,24 @@ pub(super) fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
+///
+/// Exact Fresnel reflectance of unpolarized light arriving at `cos_theta` to the normal, where
+/// `eta` is the refractive index beyond the surface over the one the light comes from
+///
+pub(crate) fn fresnel(cos_theta: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
 pub use refractive_indices::*;
 
 #[allow(dead_code)]
@@ -31,17 +51,199 @@ mod refractive_indices {
     pub static RI_DIAMOND: f64 = 2.417;
 }
 
-#[derive(Clone, Copy)]
+///
+/// Glass, water and the like. With absorption, light passing through is tinted the more the
+/// further it travels inside, following the Beer-Lambert law, so thick parts look darker than
+/// thin ones. Only the path straight from one side of the surface to the other is absorbed,
+/// not the parts of it split up by other objects inside.
+///
+#[derive(Clone)]
 pub struct Dielectric {
     ///
     /// Refractive index in vacuum or air, or ratio of the material's refractive index over the
     /// refractive index of the enclosing media
     ///
     pub(super) refraction_index: f64,
+    pub(super) distribution: Ggx, // Microfacet normals of a rough surface
+    pub(super) absorption: Color, // Share of light absorbed per unit distance inside, per channel
+    pub(super) dispersion: Option<Dispersion>, // How the refractive index varies in spectral mode
+    pub(super) film: Option<ThinFilm>, // Coating on the outside that colors reflections by interference
+}
+
+///
+/// How a refractive index varies with the wavelength, which splits white light into colors.
+/// Wavelengths are in micrometers in both formulas.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+
+    /// n² = 1 + Σ b λ² / (λ² - c), over the three terms
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7, a common optical glass
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    /// Diamond, with two terms
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [4.3356, 0.3306, 0.0],
+        c: [0.01124, 0.030625, 0.0],
+    };
+
+    ///
+    /// Refractive index at `lambda` nanometers
+    ///
+    pub fn refraction_index(&self, lambda: f64) -> f64 {
+        let micrometers = lambda / 1000.0;
+        let l2 = micrometers * micrometers;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self::rough(refraction_index, 0.0)
+    }
+
+    ///
+    /// Frosted glass, whose microfacets blur both what it reflects and what shows through.
+    /// `roughness` is in 0..=1, and 0 is the same as `new`.
+    ///
+    pub fn rough(refraction_index: f64, roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            refraction_index,
+            distribution: Ggx::from_roughness(roughness, roughness),
+            absorption: Color::black(),
+            dispersion: None,
+            film: None,
+        }
+    }
+
+    ///
+    /// Varies the refractive index with the wavelength in spectral mode. In RGB mode the index
+    /// at 587.6 nm, the yellow Fraunhofer d line that glass is usually specified at, is used
+    /// throughout.
+    ///
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.refraction_index = dispersion.refraction_index(587.6);
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    ///
+    /// Refractive index for the hero wavelength of `r_in`, or the fixed one in RGB mode
+    ///
+    pub(super) fn refraction_index_for(&self, r_in: Ray) -> f64 {
+        match (self.dispersion, r_in.wavelengths()) {
+            (Some(dispersion), Some(wavelengths)) => {
+                dispersion.refraction_index(wavelengths.hero())
+            }
+            _ => self.refraction_index,
+        }
+    }
+
+    ///
+    /// Coats the surface with `film`. With a refractive index of 1 and a film of soapy water,
+    /// this is a soap bubble.
+    ///
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    ///
+    /// Reflectance of `film` at `rec` for light arriving along `r_in` at `cos_theta` to the
+    /// normal, from whichever side `rec` was hit from, at the wavelengths `r_in` carries in
+    /// spectral mode. The light that isn't reflected is transmitted.
+    ///
+    pub(super) fn film_reflectance(
+        &self,
+        film: &ThinFilm,
+        r_in: Ray,
+        rec: &HitRecord,
+        cos_theta: f64,
+    ) -> Color {
+        film.reflectance(r_in, rec, cos_theta, |lambda| {
+            let inside = self
+                .dispersion
+                .map_or(self.refraction_index, |d| d.refraction_index(lambda));
+            if rec.front_face {
+                (1.0, inside, 0.0)
+            } else {
+                (inside, 1.0, 0.0)
+            }
+        })
+    }
+
+    ///
+    /// Absorbs `absorption` of the light per unit distance inside, for each channel
+    ///
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    ///
+    /// Lets `transmittance` of the light through after `distance` inside, for each channel.
+    /// Channels of 0 absorb everything. Panics if `distance` isn't greater than 0.
+    ///
+    pub fn with_transmittance(self, transmittance: Color, distance: f64) -> Self {
+        assert!(
+            distance > 0.0,
+            "A transmittance needs a distance greater than 0, not {distance}"
+        );
+        let absorption = |t: f64| -t.ln() / distance;
+        self.with_absorption(Color::new(
+            absorption(transmittance.r()),
+            absorption(transmittance.g()),
+            absorption(transmittance.b()),
+        ))
+    }
+
+    ///
+    /// Share of light that `r_in` carries from its origin to a hit from inside
+    ///
+    pub(super) fn transmittance(&self, r_in: Ray, rec: &HitRecord) -> Color {
+        if rec.front_face || self.absorption == Color::black() {
+            return Color::white();
+        }
+        let distance = rec.t * r_in.direction().length();
+        Color::new(
+            (-self.absorption.r() * distance).exp(),
+            (-self.absorption.g() * distance).exp(),
+            (-self.absorption.b() * distance).exp(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmittance_is_reached_at_its_distance() {
+        let glass = Dielectric::new(RI_GLASS).with_transmittance(Color::new(0.25, 1.0, 0.0), 2.0);
+        let reached = |absorption: f64| (-absorption * 2.0).exp();
+        assert!((reached(glass.absorption.r()) - 0.25).abs() < 1e-12);
+        assert_eq!(reached(glass.absorption.g()), 1.0);
+        assert_eq!(reached(glass.absorption.b()), 0.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn transmittance_needs_a_distance() {
+        let _ = Dielectric::new(RI_GLASS).with_transmittance(Color::new(0.5, 0.5, 0.5), 0.0);
     }
 }
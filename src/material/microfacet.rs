@@ -0,0 +1,241 @@
+//!
+//! The GGX (Trowbridge-Reitz) distribution of microfacet normals, with Smith masking and
+//! shadowing. Directions are in a local frame with the macro surface normal along z.
+//!
+
+use std::f64::consts::PI;
+
+use crate::{onb::Onb, vec3::Vec3};
+
+// Roughness below which a surface is treated as perfectly smooth
+pub(crate) const SMOOTH_ALPHA: f64 = 1e-3;
+
+// Smallest alpha along either axis. A surface smooth along one axis only is still sampled as a
+// rough one, where an alpha of 0 would divide by zero.
+const MIN_ALPHA: f64 = 1e-4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ggx {
+    pub(crate) alpha_x: f64, // Roughness along the local x axis
+    pub(crate) alpha_y: f64, // Roughness along the local y axis
+}
+
+impl Ggx {
+    ///
+    /// From perceptual roughness in 0..=1 along each axis, which is squared into alpha
+    ///
+    pub(crate) fn from_roughness(roughness_x: f64, roughness_y: f64) -> Self {
+        Self {
+            alpha_x: (roughness_x * roughness_x).max(MIN_ALPHA),
+            alpha_y: (roughness_y * roughness_y).max(MIN_ALPHA),
+        }
+    }
+
+    pub(crate) fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    ///
+    /// Density of microfacet normals `h`, per unit projected area of the surface
+    ///
+    pub(crate) fn d(&self, h: Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let e = (h.x() / self.alpha_x).powi(2) + (h.y() / self.alpha_y).powi(2) + h.z().powi(2);
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    ///
+    /// Smith's auxiliary function, the share of microfacets hidden from `w` relative to those
+    /// seen
+    ///
+    fn lambda(&self, w: Vec3) -> f64 {
+        let tan2 =
+            ((self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2)) / w.z().powi(2);
+        0.5 * ((1.0 + tan2).sqrt() - 1.0)
+    }
+
+    ///
+    /// Share of microfacets visible from `w`
+    ///
+    pub(crate) fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    ///
+    /// Share of microfacets visible from both `wo` and `wi`, correlated by height
+    ///
+    pub(crate) fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    ///
+    /// Density of the microfacet normals that `wo` sees, which `sample_visible_normal` draws
+    /// from
+    ///
+    pub(crate) fn visible_d(&self, wo: Vec3, h: Vec3) -> f64 {
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z().abs()
+    }
+
+    ///
+    /// A random microfacet normal, in proportion to how much of it `wo` sees (Heitz 2018).
+    /// `wo` must be above the surface.
+    ///
+    pub(crate) fn sample_visible_normal(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view so the distribution becomes a hemisphere of unit roughness
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit_vector();
+
+        let len_sq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len_sq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / len_sq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        // A point on the disk, squeezed into the part of the hemisphere facing the view
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // And unstretch it again
+        Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(0.0),
+        )
+        .unit_vector()
+    }
+}
+
+///
+/// Local frame of a rough surface with normal `normal`, its x axis along the tangent that
+/// circles the Y axis, which anisotropic roughness is measured along
+///
+pub(crate) fn tangent_frame(normal: Vec3) -> Onb {
+    let around_y = Vec3::new(0.0, 1.0, 0.0).cross(normal);
+    if around_y.length_squared() > 1e-12 {
+        Onb::with_tangent(normal, around_y)
+    } else {
+        // At the poles any tangent will do
+        Onb::with_tangent(normal, Vec3::new(1.0, 0.0, 0.0))
+    }
+}
+
+///
+/// The microfacet normal that refracts `wo` into `wi` on the other side of the surface, where
+/// `eta` is the refractive index on that side over the one on the side of `wo`. None if no
+/// microfacet facing `wo` refracts it that way.
+///
+pub(crate) fn refraction_half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+    let h = (wo + eta * wi).unit_vector();
+    let h = if h.z() < 0.0 { -h } else { h };
+    (wo.dot(h) > 0.0 && wi.dot(h) < 0.0).then_some(h)
+}
+
+///
+/// `wo` refracted through a microfacet with normal `h`, with `eta` as for
+/// `refraction_half_vector`. None on total internal reflection.
+///
+pub(crate) fn refract(wo: Vec3, h: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = wo.dot(h);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUGHNESSES: [Ggx; 3] = [
+        Ggx {
+            alpha_x: 0.5,
+            alpha_y: 0.5,
+        },
+        Ggx {
+            alpha_x: 0.2,
+            alpha_y: 0.6,
+        },
+        Ggx {
+            alpha_x: 1.0,
+            alpha_y: 1.0,
+        },
+    ];
+
+    fn direction(theta: f64, phi: f64) -> Vec3 {
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        )
+    }
+
+    // Midpoint rule over the upper hemisphere
+    fn integrate(f: impl Fn(Vec3) -> f64) -> f64 {
+        const N: usize = 600;
+        let (d_theta, d_phi) = (0.5 * PI / N as f64, 2.0 * PI / N as f64);
+        let mut sum = 0.0;
+        for i in 0..N {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..N {
+                let phi = (j as f64 + 0.5) * d_phi;
+                sum += f(direction(theta, phi)) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn microfacets_cover_the_surface_once() {
+        for ggx in ROUGHNESSES {
+            let projected = integrate(|h| ggx.d(h) * h.z());
+            assert!((projected - 1.0).abs() < 1e-3, "{ggx:?}: {projected}");
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_a_distribution() {
+        // Which only holds if G1 matches D
+        for ggx in ROUGHNESSES {
+            for theta in [0.0, 0.5, 1.2, 1.5] {
+                let wo = direction(theta, 0.7);
+                let total = integrate(|h| ggx.visible_d(wo, h));
+                assert!((total - 1.0).abs() < 2e-3, "{ggx:?} at {theta}: {total}");
+            }
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_sampled_by_their_density() {
+        const N: usize = 400;
+        for ggx in ROUGHNESSES {
+            let wo = direction(0.9, 0.3);
+
+            // Compare the mean normal drawn from a stratified grid with its expected value
+            let mut mean = Vec3::new(0.0, 0.0, 0.0);
+            for i in 0..N {
+                for j in 0..N {
+                    let (u1, u2) = ((i as f64 + 0.5) / N as f64, (j as f64 + 0.5) / N as f64);
+                    mean += ggx.sample_visible_normal(wo, u1, u2) / (N * N) as f64;
+                }
+            }
+            let expected = Vec3::new(
+                integrate(|h| h.x() * ggx.visible_d(wo, h)),
+                integrate(|h| h.y() * ggx.visible_d(wo, h)),
+                integrate(|h| h.z() * ggx.visible_d(wo, h)),
+            );
+            assert!(
+                (mean - expected).length() < 2e-3,
+                "{ggx:?}: {mean:?} against {expected:?}"
+            );
+        }
+    }
+}
@@ -1,17 +1,23 @@
+pub mod conductor;
 pub mod dielectric;
 pub mod emissive;
 pub mod isotropic;
 pub mod lambertian;
 pub mod metal;
+pub mod microfacet;
+pub mod thin_film;
 
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use dielectric::{reflectance, refract};
+use microfacet::{refraction_half_vector, tangent_frame};
 
 use crate::{
     color::Color,
     hittable::HitRecord,
+    pdf::Pdf,
     ray::Ray,
+    rng,
     vec3::{Point3, Vec3},
 };
 
@@ -20,6 +26,7 @@ use crate::{
 pub enum Material {
     Lambertian(lambertian::Lambertian),
     Metal(metal::Metal),
+    Conductor(conductor::Conductor),
     Dielectric(dielectric::Dielectric),
     DiffuseLight(emissive::DiffuseLight),
     Isotropic(isotropic::Isotropic),
@@ -49,6 +56,18 @@ impl From<metal::Metal> for Material {
     }
 }
 
+impl From<conductor::Conductor> for Arc<Material> {
+    fn from(value: conductor::Conductor) -> Self {
+        Arc::new(Material::Conductor(value))
+    }
+}
+
+impl From<conductor::Conductor> for Material {
+    fn from(value: conductor::Conductor) -> Self {
+        Material::Conductor(value)
+    }
+}
+
 impl From<dielectric::Dielectric> for Arc<Material> {
     fn from(value: dielectric::Dielectric) -> Self {
         Arc::new(Material::Dielectric(value))
@@ -79,48 +98,107 @@ impl From<isotropic::Isotropic> for Material {
     }
 }
 
+///
+/// How a material scatters an incoming ray
+///
+pub enum Scatter {
+    ///
+    /// A single outgoing ray, determined by the incoming one, as from a mirror or glass. There is
+    /// no density to weigh it by, so it is followed as is.
+    ///
+    Specular(Ray),
+
+    ///
+    /// Outgoing directions distributed according to `Material::scattering_pdf`, which `Pdf`
+    /// importance samples
+    ///
+    Pdf(Pdf<'static>),
+}
+
 impl Material {
     ///
     /// Return:
     /// - `None`, when no scattering occurs
-    /// - `Some(attenuation, bounced_ray)` when scattering occurs. `attenuation`
-    /// defines how much and in what color the ray should be attenuated by this
-    /// bounce
+    /// - `Some(attenuation, scatter)` when scattering occurs. `attenuation`
+    ///   defines how much and in what color the ray should be attenuated by this
+    ///   bounce, at the wavelengths `r_in` carries in spectral mode, and `scatter`
+    ///   how to pick the bounced ray
     ///
-    pub fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    pub fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Scatter)> {
         use Material::*;
         match self {
             Lambertian(l) => {
-                let mut scatter_dir = rec.normal + Vec3::random_unit_vector();
-
-                // Catch degenerate scatter direction
-                if scatter_dir.near_zero() {
-                    scatter_dir = rec.normal
-                }
-
-                let scattered = Ray::with_time(rec.p, scatter_dir, r_in.time());
-                let attenuation = l.tex.value(rec.u, rec.v, rec.p);
-                Some((attenuation, scattered))
+                let attenuation = r_in.uplift(l.tex.value(rec.u, rec.v, rec.p));
+                Some((attenuation, Scatter::Pdf(Pdf::cosine(rec.normal))))
             }
 
             Metal(m) => {
-                let mut reflected = r_in.direction().reflect(rec.normal);
-                reflected = reflected.unit_vector() + (m.fuzz * Vec3::random_unit_vector());
-                let scattered = Ray::with_time(rec.p, reflected, r_in.time());
+                let reflected = r_in.direction().reflect(rec.normal).unit_vector();
 
+                if m.fuzz > 0.0 {
+                    let pdf = Pdf::Fuzz {
+                        reflected,
+                        fuzz: m.fuzz,
+                    };
+                    return Some((r_in.uplift(m.albedo), Scatter::Pdf(pdf)));
+                }
+
+                let scattered = Ray::with_time(rec.p, reflected, r_in.time());
                 if scattered.direction().dot(rec.normal) > 0.0 {
-                    Some((m.albedo, scattered))
+                    Some((r_in.uplift(m.albedo), Scatter::Specular(scattered)))
                 } else {
                     None
                 }
             }
 
+            Conductor(c) => {
+                let wo = -r_in.direction().unit_vector();
+                if c.distribution.is_smooth() {
+                    let reflected = r_in.direction().reflect(rec.normal).unit_vector();
+                    let scattered = Ray::with_time(rec.p, reflected, r_in.time());
+                    let fresnel = c.fresnel(r_in, wo.dot(rec.normal), rec);
+                    return Some((fresnel, Scatter::Specular(scattered)));
+                }
+
+                let frame = tangent_frame(rec.normal);
+                let wo = frame.to_local(wo);
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let pdf = Pdf::Microfacet {
+                    frame,
+                    wo,
+                    distribution: c.distribution,
+                };
+                Some((r_in.uplift(Color::white()), Scatter::Pdf(pdf)))
+            }
+
+            Dielectric(d) if !d.distribution.is_smooth() => {
+                let frame = tangent_frame(rec.normal);
+                let wo = frame.to_local(-r_in.direction().unit_vector());
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let pdf = Pdf::RoughDielectric {
+                    frame,
+                    wo,
+                    distribution: d.distribution,
+                    eta: if rec.front_face {
+                        d.refraction_index_for(r_in)
+                    } else {
+                        1.0 / d.refraction_index_for(r_in)
+                    },
+                };
+                Some((r_in.uplift(d.transmittance(r_in, rec)), Scatter::Pdf(pdf)))
+            }
+
             Dielectric(d) => {
-                let attenuation = Color::white();
+                let mut attenuation = r_in.uplift(d.transmittance(r_in, rec));
+                let refraction_index = d.refraction_index_for(r_in);
                 let ri = if rec.front_face {
-                    1.0 / d.refraction_index
+                    1.0 / refraction_index
                 } else {
-                    d.refraction_index
+                    refraction_index
                 };
 
                 let unit_dir = r_in.direction().unit_vector();
@@ -130,28 +208,181 @@ impl Material {
 
                 let cannot_refract = ri * sin_theta > 1.0;
 
-                let direction =
-                    if cannot_refract || reflectance(cos_theta, ri) > rand::random::<f64>() {
-                        // Reflect
-                        Vec3::reflect(&unit_dir, rec.normal)
-                    } else {
-                        // Refract
-                        refract(unit_dir, rec.normal, ri)
-                    };
+                let direction = match &d.film {
+                    _ if cannot_refract => Vec3::reflect(&unit_dir, rec.normal),
+                    Some(film) => {
+                        // Each color reflects its own share, so choose by the average and weigh
+                        // the colors by how much more or less likely they are
+                        let film_reflectance = d.film_reflectance(film, r_in, rec, cos_theta);
+                        let p =
+                            (film_reflectance.r() + film_reflectance.g() + film_reflectance.b())
+                                / 3.0;
+                        if p > rng::random::<f64>() {
+                            attenuation = attenuation * film_reflectance / p;
+                            Vec3::reflect(&unit_dir, rec.normal)
+                        } else {
+                            attenuation =
+                                attenuation * (Color::white() - film_reflectance) / (1.0 - p);
+                            refract(unit_dir, rec.normal, ri)
+                        }
+                    }
+                    None => {
+                        if reflectance(cos_theta, ri) > rng::random::<f64>() {
+                            // Reflect
+                            Vec3::reflect(&unit_dir, rec.normal)
+                        } else {
+                            // Refract
+                            refract(unit_dir, rec.normal, ri)
+                        }
+                    }
+                };
 
-                Some((attenuation, Ray::with_time(rec.p, direction, r_in.time())))
+                let scattered = Ray::with_time(rec.p, direction, r_in.time());
+                Some((attenuation, Scatter::Specular(scattered)))
             }
 
             DiffuseLight(_) => None,
 
             Isotropic(i) => {
-                let scattered = Ray::with_time(rec.p, Vec3::random_unit_vector(), r_in.time());
-                let attenuation = i.tex.value(rec.u, rec.v, rec.p);
-                Some((attenuation, scattered))
+                let attenuation = r_in.uplift(i.tex.value(rec.u, rec.v, rec.p));
+                Some((attenuation, Scatter::Pdf(Pdf::Sphere)))
+            }
+        }
+    }
+
+    ///
+    /// Density of the material scattering `r_in` into `scattered`, per unit solid angle. Scaled
+    /// by the attenuation, this is how much light arriving along `scattered` is reflected back
+    /// along `r_in`. Always 0 for materials that scatter specularly.
+    ///
+    pub fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        use Material::*;
+        match self {
+            Lambertian(_) => {
+                let cos_theta = rec.normal.dot(scattered.direction().unit_vector());
+                f64::max(0.0, cos_theta / PI)
+            }
+
+            Metal(m) if m.fuzz > 0.0 => {
+                // Rays fuzzed to below the surface are absorbed
+                if scattered.direction().dot(rec.normal) <= 0.0 {
+                    return 0.0;
+                }
+                let reflected = r_in.direction().reflect(rec.normal).unit_vector();
+                Pdf::Fuzz {
+                    reflected,
+                    fuzz: m.fuzz,
+                }
+                .value(*scattered.direction())
+            }
+
+            Conductor(c) if !c.distribution.is_smooth() => {
+                let frame = tangent_frame(rec.normal);
+                let wo = frame.to_local(-r_in.direction().unit_vector());
+                let wi = frame.to_local(scattered.direction().unit_vector());
+                if wo.z() <= 0.0 || wi.z() <= 0.0 {
+                    return 0.0;
+                }
+                let h = (wo + wi).unit_vector();
+                let d = &c.distribution;
+                d.d(h) * d.g(wo, wi) / (4.0 * wo.z())
+            }
+
+            Dielectric(d) if !d.distribution.is_smooth() => {
+                let eta = if rec.front_face {
+                    d.refraction_index_for(r_in)
+                } else {
+                    1.0 / d.refraction_index_for(r_in)
+                };
+                let frame = tangent_frame(rec.normal);
+                let wo = frame.to_local(-r_in.direction().unit_vector());
+                let wi = frame.to_local(scattered.direction().unit_vector());
+                if wo.z() <= 0.0 || wi.z() == 0.0 {
+                    return 0.0;
+                }
+                let dist = &d.distribution;
+
+                if wi.z() > 0.0 {
+                    let h = (wo + wi).unit_vector();
+                    let f = dielectric::fresnel(wo.dot(h), eta);
+                    return f * dist.d(h) * dist.g(wo, wi) / (4.0 * wo.z());
+                }
+
+                // Walter et al. 2007, without the change in radiance across the surface, which
+                // smooth glass leaves out as well
+                let Some(h) = refraction_half_vector(wo, wi, eta) else {
+                    return 0.0;
+                };
+                let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+                let t = 1.0 - dielectric::fresnel(cos_o, eta);
+                t * dist.d(h) * dist.g(wo, wi) * cos_o * cos_i.abs()
+                    / (wo.z() * (cos_i + cos_o / eta).powi(2))
+            }
+
+            Isotropic(_) => 1.0 / (4.0 * PI),
+
+            _ => 0.0,
+        }
+    }
+
+    ///
+    /// Color that light scattered from `scattered` into `r_in` takes on besides the attenuation,
+    /// for materials whose color depends on the directions, like the Fresnel reflectance of
+    /// conductors. White for everything else. In spectral mode, its values at the wavelengths
+    /// `r_in` carries.
+    ///
+    pub fn scattering_tint(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        use Material::*;
+        match self {
+            Conductor(c) => {
+                let wo = -r_in.direction().unit_vector();
+                let h = (wo + scattered.direction().unit_vector()).unit_vector();
+                c.fresnel(r_in, wo.dot(h), rec)
             }
+
+            Dielectric(d) if !d.distribution.is_smooth() && d.film.is_some() => {
+                // The film's reflectance in place of the bare surface's, which scattering_pdf
+                // weighs the two lobes by
+                let film = d.film.as_ref().unwrap();
+                let eta = if rec.front_face {
+                    d.refraction_index_for(r_in)
+                } else {
+                    1.0 / d.refraction_index_for(r_in)
+                };
+                let frame = tangent_frame(rec.normal);
+                let wo = frame.to_local(-r_in.direction().unit_vector());
+                let wi = frame.to_local(scattered.direction().unit_vector());
+                let h = if wi.z() > 0.0 {
+                    Some((wo + wi).unit_vector())
+                } else {
+                    refraction_half_vector(wo, wi, eta)
+                };
+                let Some(h) = h else {
+                    return r_in.uplift(Color::white());
+                };
+
+                let cos_theta = wo.dot(h);
+                let fresnel = dielectric::fresnel(cos_theta, eta);
+                let film_reflectance = d.film_reflectance(film, r_in, rec, cos_theta);
+                if wi.z() > 0.0 {
+                    film_reflectance / fresnel.max(1e-6)
+                } else {
+                    (Color::white() - film_reflectance) / (1.0 - fresnel).max(1e-6)
+                }
+            }
+
+            _ => r_in.uplift(Color::white()),
         }
     }
 
+    ///
+    /// Whether the material bends each wavelength differently in spectral mode, so that a ray
+    /// scattered off it can only carry one
+    ///
+    pub fn is_dispersive(&self) -> bool {
+        matches!(self, Material::Dielectric(d) if d.dispersion.is_some())
+    }
+
     pub fn emitted(&self, u: f64, v: f64, p: Point3) -> Color {
         use Material::*;
         match self {
@@ -162,3 +393,126 @@ impl Material {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::dielectric::Dielectric;
+
+    const SAMPLES: usize = 20_000;
+
+    // A hit on the plane z = 0, from above if `front_face`
+    fn hit(mat: &Material, front_face: bool) -> HitRecord {
+        HitRecord {
+            p: Point3::new(0.0, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, if front_face { 1.0 } else { -1.0 }),
+            mat: mat.clone(),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face,
+        }
+    }
+
+    // A ray arriving at the origin at `theta` to the normal on the side `front_face` picks
+    fn arriving(theta: f64, front_face: bool) -> Ray {
+        let z = if front_face { -1.0 } else { 1.0 };
+        let direction = Vec3::new(theta.sin(), 0.0, z * theta.cos());
+        Ray::new(Point3::new(0.0, 0.0, 0.0) - direction, direction)
+    }
+
+    #[test]
+    fn rough_dielectric_does_not_create_energy() {
+        rng::start_stream(&[22]);
+        for front_face in [true, false] {
+            for theta in [0.0, 0.6, 1.3] {
+                let r_in = arriving(theta, front_face);
+                let mut albedos = Vec::new();
+                for roughness in [0.1, 0.4, 0.8] {
+                    let mat: Material = Dielectric::rough(1.5, roughness).into();
+                    let rec = hit(&mat, front_face);
+                    let Some((_, Scatter::Pdf(pdf))) = mat.scatter(r_in, &rec) else {
+                        panic!("rough glass scatters by a pdf");
+                    };
+
+                    // Sampling the BSDF by its own lobes, each sample carries the share of
+                    // microfacets that are visible from both directions, at most all of them
+                    let mut albedo = 0.0;
+                    for _ in 0..SAMPLES {
+                        let scattered = Ray::new(rec.p, pdf.generate());
+                        let pdf_value = pdf.value(*scattered.direction());
+                        if pdf_value == 0.0 {
+                            continue;
+                        }
+                        let weight = mat.scattering_pdf(r_in, &rec, scattered) / pdf_value;
+                        assert!(weight <= 1.0 + 1e-9, "{roughness} at {theta}: {weight}");
+                        albedo += weight / SAMPLES as f64;
+                    }
+                    albedos.push(albedo);
+                }
+
+                // Only light that scatters between microfacets more than once is lost, which
+                // barely happens on smoother glass
+                let case = format!("at {theta}, front face {front_face}: {albedos:?}");
+                assert!(albedos[0] > 0.995, "{case}");
+                assert!(albedos.windows(2).all(|a| a[0] > a[1]), "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_at_roughness_0_is_smooth() {
+        rng::start_stream(&[22]);
+        let theta: f64 = 0.8;
+        let eta = 1.5;
+        let snell = Vec3::new(
+            theta.sin() / eta,
+            0.0,
+            -(1.0 - (theta.sin() / eta).powi(2)).sqrt(),
+        );
+        let mirror = Vec3::new(theta.sin(), 0.0, theta.cos());
+        let r_in = arriving(theta, true);
+
+        let mat: Material = Dielectric::rough(eta, 0.0).into();
+        let rec = hit(&mat, true);
+        let mut reflected = 0;
+        for _ in 0..SAMPLES {
+            let Some((attenuation, Scatter::Specular(scattered))) = mat.scatter(r_in, &rec) else {
+                panic!("smooth glass scatters specularly");
+            };
+            assert_eq!(attenuation, Color::white());
+            assert_eq!(mat.scattering_pdf(r_in, &rec, scattered), 0.0);
+
+            let direction = scattered.direction().unit_vector();
+            if (direction - mirror).length() < 1e-9 {
+                reflected += 1;
+            } else {
+                assert!((direction - snell).length() < 1e-9, "{direction:?}");
+            }
+        }
+        let share = reflected as f64 / SAMPLES as f64;
+        assert!((share - reflectance(theta.cos(), 1.0 / eta)).abs() < 0.01);
+
+        // And the rougher it gets, the further the rays spread around those of smooth glass
+        let mut spread = Vec::new();
+        for roughness in [0.05, 0.2, 0.5] {
+            let mat: Material = Dielectric::rough(eta, roughness).into();
+            let rec = hit(&mat, true);
+            let Some((_, Scatter::Pdf(pdf))) = mat.scatter(r_in, &rec) else {
+                panic!("rough glass scatters by a pdf");
+            };
+            let mut angle = 0.0;
+            let mut refracted = 0;
+            for _ in 0..SAMPLES {
+                let direction = pdf.generate().unit_vector();
+                if direction.z() < 0.0 {
+                    angle += direction.dot(snell).clamp(-1.0, 1.0).acos();
+                    refracted += 1;
+                }
+            }
+            spread.push(angle / refracted as f64);
+        }
+        assert!(spread[0] < 0.02, "{spread:?}");
+        assert!(spread.windows(2).all(|s| s[0] < s[1]), "{spread:?}");
+    }
+}
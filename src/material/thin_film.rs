@@ -0,0 +1,208 @@
+//!
+//! Thin-film interference, the colors of soap bubbles, oil slicks and heat-tinted metal. Light
+//! reflected off the top and the bottom of a film a few hundred nanometers thick interferes,
+//! strengthening some wavelengths and cancelling others depending on the thickness and angle.
+//!
+
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    ray::Ray,
+    spectrum,
+    texture::{solid_color::SolidColor, Texture},
+};
+
+///
+/// A transparent coating on a dielectric or conductor
+///
+#[derive(Clone)]
+pub struct ThinFilm {
+    pub(super) refraction_index: f64,
+    pub(super) thickness: Texture, // Brightness scales the thickness, from 0 at black to all of it at white
+    pub(super) max_thickness: f64, // Thickness in nanometers where the texture is white
+}
+
+impl ThinFilm {
+    ///
+    /// A film `thickness` nanometers thick all over
+    ///
+    pub fn new(refraction_index: f64, thickness: f64) -> Self {
+        Self::textured(
+            refraction_index,
+            SolidColor::new(Color::white()).into(),
+            thickness,
+        )
+    }
+
+    ///
+    /// A film as thick as the brightness of `thickness`, times `max_thickness` nanometers. Soap
+    /// films, which drain thinner towards the top and swirl, can follow a noise texture.
+    ///
+    pub fn textured(refraction_index: f64, thickness: Texture, max_thickness: f64) -> Self {
+        Self {
+            refraction_index,
+            thickness,
+            max_thickness,
+        }
+    }
+
+    ///
+    /// Reflectance at `rec` for light arriving along `r_in` at `cos_theta` to the normal, as the
+    /// RGB color of the reflected spectrum, or in spectral mode its values at the wavelengths
+    /// `r_in` carries. `media(lambda)` gives, at `lambda` nanometers, the refractive index of the
+    /// side the light comes from, and the real part and extinction coefficient of the one beyond
+    /// the film.
+    ///
+    pub(super) fn reflectance(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        cos_theta: f64,
+        media: impl Fn(f64) -> (f64, f64, f64),
+    ) -> Color {
+        let brightness = self.thickness.value(rec.u, rec.v, rec.p);
+        let thickness =
+            self.max_thickness * (brightness.r() + brightness.g() + brightness.b()) / 3.0;
+        let cos_theta = cos_theta.clamp(0.0, 1.0);
+
+        let reflectance = |lambda| {
+            let (incident, eta, k) = media(lambda);
+            airy(
+                cos_theta,
+                incident,
+                self.refraction_index,
+                Complex::new(eta, k),
+                thickness,
+                lambda,
+            )
+        };
+        if let Some(wavelengths) = r_in.wavelengths() {
+            return wavelengths.map(reflectance);
+        }
+
+        // Saturated spectra can fall outside the RGB gamut, keep them within what a surface reflects
+        let rgb = spectrum::reflectance_to_rgb(reflectance);
+        Color::new(
+            rgb.r().clamp(0.0, 1.0),
+            rgb.g().clamp(0.0, 1.0),
+            rgb.b().clamp(0.0, 1.0),
+        )
+    }
+}
+
+///
+/// Reflectance of unpolarized light off a film of index `film` and `thickness` nanometers,
+/// between a medium of index `incident` and one of complex index `beyond`, at `lambda`
+/// nanometers. Sums the light bouncing any number of times inside the film, by the Airy
+/// formula.
+///
+fn airy(
+    cos_theta: f64,
+    incident: f64,
+    film: f64,
+    beyond: Complex,
+    thickness: f64,
+    lambda: f64,
+) -> f64 {
+    // n cos(theta) in each layer, by Snell's law. Complex past total internal reflection or in
+    // a conductor, with the root that decays away from the film.
+    let sin2 = 1.0 - cos_theta * cos_theta;
+    let along = |n: Complex| (n * n - Complex::real(incident * incident * sin2)).sqrt();
+    let (n1, n2, n3) = (Complex::real(incident), Complex::real(film), beyond);
+    let (q1, q2, q3) = (along(n1), along(n2), along(n3));
+
+    // Phase the light gains crossing the film and back
+    let phase = (Complex::new(0.0, 4.0 * PI * thickness / lambda) * q2).exp();
+
+    let reflectance = |r12: Complex, r23: Complex| {
+        let r = (r12 + r23 * phase) / (Complex::real(1.0) + r12 * r23 * phase);
+        r.norm_sqr()
+    };
+    let s = |qi: Complex, qj: Complex| (qi - qj) / (qi + qj);
+    let p = |ni: Complex, qi: Complex, nj: Complex, qj: Complex| {
+        (nj * nj * qi - ni * ni * qj) / (nj * nj * qi + ni * ni * qj)
+    };
+
+    let r_s = reflectance(s(q1, q2), s(q2, q3));
+    let r_p = reflectance(p(n1, q1, n2, q2), p(n2, q2, n3, q3));
+    (0.5 * (r_s + r_p)).clamp(0.0, 1.0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    ///
+    /// Principal square root, with a non-negative real part
+    ///
+    fn sqrt(self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = (0.5 * (norm + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (norm - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(self) -> Self {
+        let scale = self.re.exp();
+        Self::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let denominator = rhs.norm_sqr();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
@@ -0,0 +1,452 @@
+use std::ops::Mul;
+
+use crate::vec3::{Point3, Vec3};
+
+///
+/// A 4x4 matrix for affine transforms of points and vectors, stored by rows. Points are treated
+/// as columns with a w of 1, so `a * b` applies `b` first.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4([[f64; 4]; 4]);
+
+impl Matrix4 {
+    pub fn new(rows: [[f64; 4]; 4]) -> Self {
+        Self(rows)
+    }
+
+    pub fn identity() -> Self {
+        Self([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// Scales by a separate factor along each axis
+    ///
+    pub fn scaling(factors: Vec3) -> Self {
+        Self([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// Rotates `angle` degrees around the X axis, turning Y towards Z
+    ///
+    pub fn rotation_x(angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// Rotates `angle` degrees around the Y axis, turning Z towards X, like `YRotate`
+    ///
+    pub fn rotation_y(angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// Rotates `angle` degrees around the Z axis, turning X towards Y
+    ///
+    pub fn rotation_z(angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// Rotates `angle` degrees counterclockwise around `axis`, looking down the axis towards the
+    /// origin
+    ///
+    pub fn rotation(axis: Vec3, angle: f64) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        Self([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// Places an object at `from`, with its -Z axis pointing at `at` and its Y axis as close to
+    /// `up` as possible. This is how the camera is oriented, so a model built looking down -Z
+    /// faces the same way as a camera with the same arguments.
+    ///
+    pub fn look_at(from: Point3, at: Point3, up: Vec3) -> Self {
+        let w = (from - at).unit_vector();
+        let u = up.cross(w).unit_vector();
+        let v = w.cross(u);
+        Self([
+            [u.x(), v.x(), w.x(), from.x()],
+            [u.y(), v.y(), w.y(), from.y()],
+            [u.z(), v.z(), w.z(), from.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    ///
+    /// This transform followed by `next`
+    ///
+    pub fn then(self, next: Matrix4) -> Self {
+        next * self
+    }
+
+    pub fn transpose(&self) -> Self {
+        let m = &self.0;
+        Self(std::array::from_fn(|i| std::array::from_fn(|j| m[j][i])))
+    }
+
+    ///
+    /// Whether the last row is [0, 0, 0, 1], give or take rounding. Other matrices are projective,
+    /// and would bend the straight rays that transforms rely on.
+    ///
+    pub fn is_affine(&self) -> bool {
+        const EPSILON: f64 = 1e-9;
+        self.0[3]
+            .iter()
+            .zip([0.0, 0.0, 0.0, 1.0])
+            .all(|(a, b)| (a - b).abs() <= EPSILON)
+    }
+
+    pub fn last_row(&self) -> [f64; 4] {
+        self.0[3]
+    }
+
+    ///
+    /// Returns None when the matrix is singular, such as a scale by 0
+    ///
+    pub fn inverse(&self) -> Option<Self> {
+        // Gauss-Jordan elimination with partial pivoting, turning `m` into the identity while
+        // applying the same row operations to `inv`
+        let mut m = self.0;
+        let mut inv = Self::identity().0;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))
+                .unwrap();
+            if m[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / m[col][col];
+            for j in 0..4 {
+                m[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = m[row][col];
+                    for j in 0..4 {
+                        m[row][j] -= factor * m[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self(inv))
+    }
+
+    ///
+    /// Determinant of the upper left 3x3 part, how much the transform scales volumes
+    ///
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.0;
+        Point3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    ///
+    /// Transforms a direction or offset, which unlike a point isn't translated
+    ///
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    ///
+    /// Transforms a vector by this matrix's transpose. Normals are transformed by the transpose
+    /// of the inverse, so that they stay perpendicular to surfaces that are scaled unevenly.
+    ///
+    pub fn transform_transposed(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v.x() + m[1][0] * v.y() + m[2][0] * v.z(),
+            m[0][1] * v.x() + m[1][1] * v.y() + m[2][1] * v.z(),
+            m[0][2] * v.x() + m[1][2] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let (a, b) = (&self.0, &rhs.0);
+        Self(std::array::from_fn(|i| {
+            std::array::from_fn(|j| (0..4).map(|k| a[i][k] * b[k][j]).sum())
+        }))
+    }
+}
+
+///
+/// A unit quaternion, representing a rotation
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Self {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    ///
+    /// Rotates `angle` degrees counterclockwise around `axis`, like `Matrix4::rotation`
+    ///
+    pub fn from_axis_angle(axis: Vec3, angle: f64) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = (0.5 * angle.to_radians()).sin_cos();
+        Self {
+            w: cos,
+            x: a.x() * sin,
+            y: a.y() * sin,
+            z: a.z() * sin,
+        }
+    }
+
+    fn dot(&self, other: &Self) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    ///
+    /// Angle in radians of the rotation from this one to `other`, the shorter way around
+    ///
+    pub fn angle_to(&self, other: &Self) -> f64 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    ///
+    /// Spherical linear interpolation, turning at a constant rate from this rotation at t = 0 to
+    /// `other` at t = 1, the shorter way around
+    ///
+    pub fn slerp(&self, other: &Self, t: f64) -> Self {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        if cos < 0.0 {
+            // q and -q are the same rotation, pick the one closer to this
+            cos = -cos;
+            other = Self {
+                w: -other.w,
+                x: -other.x,
+                y: -other.y,
+                z: -other.z,
+            };
+        }
+
+        let (a, b) = if cos > 0.9995 {
+            // Nearly the same rotation, where linear interpolation is accurate and stable
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Self {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        }
+        .normalized()
+    }
+
+    fn normalized(&self) -> Self {
+        let len = self.dot(self).sqrt();
+        Self {
+            w: self.w / len,
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len,
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let Self { w, x, y, z } = *self;
+        Matrix4([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Self;
+
+    ///
+    /// The rotation `rhs` followed by this one
+    ///
+    fn mul(self, rhs: Self) -> Self::Output {
+        let (a, b) = (self, rhs);
+        Self {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Matrix4, b: Matrix4) {
+        for (row_a, row_b) in a.0.iter().zip(b.0.iter()) {
+            for (x, y) in row_a.iter().zip(row_b) {
+                assert!((x - y).abs() < 1e-12, "{a:?} != {b:?}");
+            }
+        }
+    }
+
+    fn placement() -> Matrix4 {
+        Matrix4::scaling(Vec3::new(2.0, 0.5, 3.0))
+            .then(Matrix4::rotation(Vec3::new(1.0, 2.0, -1.0), 35.0))
+            .then(Matrix4::translation(Vec3::new(4.0, -1.0, 7.0)))
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = placement();
+        let inverse = m.inverse().unwrap();
+        assert_near(inverse * m, Matrix4::identity());
+        assert_near(m * inverse, Matrix4::identity());
+
+        let p = Point3::new(1.0, -2.0, 0.5);
+        let back = inverse.transform_point(m.transform_point(p));
+        assert!((back - p).length() < 1e-12);
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        let flattened = placement().then(Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0)));
+        assert!(flattened.inverse().is_none());
+        assert!(flattened.determinant3().abs() < 1e-12);
+    }
+
+    #[test]
+    fn projective_last_row_is_not_affine() {
+        assert!(placement().is_affine());
+
+        let mut rows = placement().0;
+        rows[3] = [0.0, 0.0, 1.0, 0.0];
+        let projective = Matrix4::new(rows);
+        assert!(!projective.is_affine());
+        assert_eq!(projective.last_row(), [0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_uneven_scale() {
+        // The plane x + y = 0, squashed along X
+        let m = Matrix4::scaling(Vec3::new(2.0, 1.0, 1.0)).then(Matrix4::rotation_z(30.0));
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+
+        let tangent = m.transform_vector(tangent);
+        let normal = m.inverse().unwrap().transform_transposed(normal);
+        assert!(tangent.dot(normal).abs() < 1e-12);
+
+        // Transforming the normal like a direction would tilt it off the surface
+        let naive = m.transform_vector(Vec3::new(1.0, 1.0, 0.0));
+        assert!(tangent.dot(naive).abs() > 0.1);
+    }
+}
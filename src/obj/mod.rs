@@ -0,0 +1,380 @@
+mod mtl;
+
+use std::{collections::HashMap, fmt::Display, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    color::Color,
+    hittable::{
+        mesh::{Face, Mesh},
+        Hittable,
+    },
+    material::{lambertian::Lambertian, Material},
+    vec3::{Point3, Vec3},
+};
+
+///
+/// Loads a Wavefront OBJ file as a single `Mesh`, using the materials from any `mtllib` it
+/// references. Faces that come before any `usemtl` statement are grey Lambertian.
+///
+pub fn load(path: impl AsRef<Path>) -> Result<Hittable> {
+    load_with_default(path, Lambertian::new(Color::new(0.73, 0.73, 0.73)))
+}
+
+///
+/// Loads a Wavefront OBJ file as a single `Mesh`, using `default_mat` for every face that comes
+/// before any `usemtl` statement.
+///
+pub fn load_with_default(
+    path: impl AsRef<Path>,
+    default_mat: impl Into<Material>,
+) -> Result<Hittable> {
+    let path = path.as_ref();
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    let buffers = parse(&contents, path, default_mat.into())?;
+
+    Ok(Mesh::new(
+        buffers.positions,
+        buffers.normals,
+        buffers.uvs,
+        buffers.faces,
+        buffers.materials,
+    )
+    .with_context(|| path.display().to_string())?
+    .into())
+}
+
+///
+/// The buffers of a mesh as read from an OBJ file, before `Mesh::new` checks them
+///
+struct MeshBuffers {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    faces: Vec<Face>,
+    materials: Vec<Material>,
+}
+
+///
+/// Parses the `contents` of the OBJ file at `path`, which errors are reported against and
+/// `mtllib` files are found next to.
+///
+fn parse(contents: &str, path: &Path, default_mat: Material) -> Result<MeshBuffers> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut faces: Vec<Face> = Vec::new();
+
+    // Every material known from mtllib statements, and the ones actually used by faces
+    let mut library: HashMap<String, Material> = HashMap::new();
+    let mut materials: Vec<Material> = vec![default_mat];
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    for (index, line) in contents.lines().enumerate() {
+        let loc = Location::new(path, index + 1);
+
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let values = parse_floats(&args, &loc)?;
+                match values[..] {
+                    [x, y, z] | [x, y, z, _] => positions.push(Point3::new(x, y, z)),
+                    _ => bail!(
+                        "{loc}: expected 3 vertex coordinates, found {}",
+                        values.len()
+                    ),
+                }
+            }
+            "vn" => {
+                let values = parse_floats(&args, &loc)?;
+                match values[..] {
+                    [x, y, z] => normals.push(Vec3::new(x, y, z)),
+                    _ => bail!(
+                        "{loc}: expected 3 normal components, found {}",
+                        values.len()
+                    ),
+                }
+            }
+            "vt" => {
+                let values = parse_floats(&args, &loc)?;
+                match values[..] {
+                    [u] => uvs.push((u, 0.0)),
+                    [u, v] | [u, v, _] => uvs.push((u, v)),
+                    _ => bail!(
+                        "{loc}: expected 1 to 3 texture coordinates, found {}",
+                        values.len()
+                    ),
+                }
+            }
+            "f" => {
+                if args.len() < 3 {
+                    bail!(
+                        "{loc}: a face needs at least 3 vertices, found {}",
+                        args.len()
+                    );
+                }
+                let corners = args
+                    .iter()
+                    .map(|corner| {
+                        parse_corner(corner, positions.len(), uvs.len(), normals.len(), &loc)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                // Every corner must agree on which attributes are present
+                let has_uv = corners[0].uv.is_some();
+                let has_normal = corners[0].normal.is_some();
+                if corners
+                    .iter()
+                    .any(|c| c.uv.is_some() != has_uv || c.normal.is_some() != has_normal)
+                {
+                    bail!("{loc}: face vertices mix different v/vt/vn layouts");
+                }
+
+                // Triangulate polygons as a fan around the first vertex
+                for i in 1..(corners.len() - 1) {
+                    let tri = [&corners[0], &corners[i], &corners[i + 1]];
+                    faces.push(Face {
+                        vertices: tri.map(|c| c.position),
+                        normals: has_normal.then(|| tri.map(|c| c.normal.unwrap_or_default())),
+                        uvs: has_uv.then(|| tri.map(|c| c.uv.unwrap_or_default())),
+                        material: current_material,
+                    });
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    bail!("{loc}: mtllib is missing a file name");
+                }
+                for file in args {
+                    let lib = mtl::load_mtl(&dir.join(file)).with_context(|| format!("{loc}"))?;
+                    library.extend(lib);
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                if name.is_empty() {
+                    bail!("{loc}: usemtl is missing a material name");
+                }
+                current_material = match material_indices.get(&name) {
+                    Some(&idx) => idx,
+                    None => {
+                        let mat = library
+                            .get(&name)
+                            .ok_or_else(|| anyhow!("{loc}: unknown material `{name}`"))?;
+                        materials.push(mat.clone());
+                        material_indices.insert(name, materials.len() - 1);
+                        materials.len() - 1
+                    }
+                };
+            }
+            // Grouping, smoothing groups, free-form geometry and everything else doesn't
+            // affect the rendered surface
+            _ => {}
+        }
+    }
+
+    if faces.is_empty() {
+        bail!("{}: file contains no faces", path.display());
+    }
+
+    Ok(MeshBuffers {
+        positions,
+        normals,
+        uvs,
+        faces,
+        materials,
+    })
+}
+
+///
+/// File and line of a statement, for error messages.
+///
+struct Location<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Location<'a> {
+    fn new(path: &'a Path, line: usize) -> Self {
+        Self { path, line }
+    }
+}
+
+impl Display for Location<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+fn parse_floats(args: &[&str], loc: &Location) -> Result<Vec<f64>> {
+    args.iter()
+        .map(|a| {
+            a.parse::<f64>()
+                .map_err(|_| anyhow!("{loc}: `{a}` is not a number"))
+        })
+        .collect()
+}
+
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+///
+/// Parses one `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner into zero-based indices.
+///
+fn parse_corner(
+    corner: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+    loc: &Location,
+) -> Result<Corner> {
+    let mut parts = corner.split('/');
+    let position = parts.next().unwrap_or("");
+    let uv = parts.next().filter(|s| !s.is_empty());
+    let normal = parts.next().filter(|s| !s.is_empty());
+    if parts.next().is_some() {
+        bail!("{loc}: malformed face vertex `{corner}`");
+    }
+
+    Ok(Corner {
+        position: resolve_index(position, position_count, "vertex", loc)?,
+        uv: uv
+            .map(|i| resolve_index(i, uv_count, "texture coordinate", loc))
+            .transpose()?,
+        normal: normal
+            .map(|i| resolve_index(i, normal_count, "normal", loc))
+            .transpose()?,
+    })
+}
+
+///
+/// OBJ indices are one-based, and negative indices count backwards from the most recent element.
+///
+fn resolve_index(index: &str, count: usize, kind: &str, loc: &Location) -> Result<usize> {
+    let i: i64 = index
+        .parse()
+        .map_err(|_| anyhow!("{loc}: `{index}` is not a valid {kind} index"))?;
+
+    let resolved = match i {
+        1.. => i - 1,
+        0 => bail!("{loc}: {kind} indices start at 1, found 0"),
+        _ => count as i64 + i,
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        bail!("{loc}: {kind} index {i} is out of range, only {count} defined so far");
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(contents: &str) -> Result<MeshBuffers> {
+        parse(
+            contents,
+            Path::new("test.obj"),
+            Lambertian::new(Color::white()).into(),
+        )
+    }
+
+    const SQUARE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+";
+
+    #[test]
+    fn polygons_become_a_fan_of_triangles() {
+        let obj = parse_str(&format!("{SQUARE}f 1 2 3 4\nf 1 2 3")).unwrap();
+        let vertices: Vec<_> = obj.faces.iter().map(|f| f.vertices).collect();
+        assert_eq!(vertices, [[0, 1, 2], [0, 2, 3], [0, 1, 2]]);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_element() {
+        let obj = parse_str(&format!("{SQUARE}f -4/-4/-1 -3/-3/-1 -2/-2/-1 -1/-1/-1")).unwrap();
+        assert_eq!(obj.faces[1].vertices, [0, 2, 3]);
+        assert_eq!(obj.faces[1].uvs, Some([0, 2, 3]));
+        assert_eq!(obj.faces[1].normals, Some([0, 0, 0]));
+
+        // Counting back from the elements defined so far, not from the end of the file
+        let obj = parse_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\nf -4 -3 -2").unwrap();
+        assert_eq!(obj.faces[0].vertices, obj.faces[1].vertices);
+    }
+
+    #[test]
+    fn corners_take_every_vertex_layout() {
+        let face = |corners: &str| parse_str(&format!("{SQUARE}f {corners}")).unwrap().faces[0];
+
+        let f = face("1 2 3");
+        assert_eq!((f.vertices, f.uvs, f.normals), ([0, 1, 2], None, None));
+        let f = face("1/2 2/3 3/4");
+        assert_eq!(
+            (f.vertices, f.uvs, f.normals),
+            ([0, 1, 2], Some([1, 2, 3]), None)
+        );
+        let f = face("1//1 2//1 3//1");
+        assert_eq!(
+            (f.vertices, f.uvs, f.normals),
+            ([0, 1, 2], None, Some([0, 0, 0]))
+        );
+        let f = face("4/1/1 3/2/1 2/3/1");
+        assert_eq!(
+            (f.vertices, f.uvs, f.normals),
+            ([3, 2, 1], Some([0, 1, 2]), Some([0, 0, 0]))
+        );
+
+        let mixed = parse_str(&format!("{SQUARE}f 1/1 2//1 3"));
+        assert!(mixed.is_err());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |contents: &str| parse_str(contents).err().unwrap().to_string();
+
+        assert_eq!(
+            error("v 0 0 0\n\n# comment\nv 1 x 0"),
+            "test.obj:4: `x` is not a number"
+        );
+        assert_eq!(
+            error(&format!("{SQUARE}f 1 2 5")),
+            "test.obj:10: vertex index 5 is out of range, only 4 defined so far"
+        );
+        assert_eq!(
+            error(&format!("{SQUARE}f 1 2 -5")),
+            "test.obj:10: vertex index -5 is out of range, only 4 defined so far"
+        );
+        assert_eq!(
+            error("v 0 0 0\nf 0 1 1"),
+            "test.obj:2: vertex indices start at 1, found 0"
+        );
+        assert_eq!(
+            error("v 0 0 0\nusemtl red"),
+            "test.obj:2: unknown material `red`"
+        );
+        assert_eq!(error(SQUARE), "test.obj: file contains no faces");
+    }
+}
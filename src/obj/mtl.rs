@@ -0,0 +1,236 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    color::Color,
+    material::{
+        dielectric::{Dielectric, RI_GLASS},
+        emissive::DiffuseLight,
+        lambertian::Lambertian,
+        metal::Metal,
+        Material,
+    },
+    texture::image::Image,
+};
+
+use super::{parse_floats, Location};
+
+///
+/// The subset of an MTL material description that maps onto the materials of this raytracer.
+///
+struct MtlMaterial {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ni: Option<f64>,
+    ns: Option<f64>,
+    dissolve: f64,
+    illum: Option<u32>,
+    map_kd: Option<Image>,
+}
+
+impl MtlMaterial {
+    fn new() -> Self {
+        Self {
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::black(),
+            ke: Color::black(),
+            ni: None,
+            ns: None,
+            dissolve: 1.0,
+            illum: None,
+            map_kd: None,
+        }
+    }
+
+    ///
+    /// Picks the closest material for the description:
+    /// - any emission (`Ke`) makes a `DiffuseLight`
+    /// - transparency (`d` < 1, or a refracting `illum` model) makes a `Dielectric` with index `Ni`
+    /// - a reflecting `illum` model, or a specular color without a diffuse one, makes a `Metal`
+    ///   colored by `Ks`, with its fuzz derived from the `Ns` exponent
+    /// - everything else is `Lambertian`, textured by `map_Kd` if given and colored by `Kd` if not
+    ///
+    fn into_material(self) -> Material {
+        let is_black = |c: Color| c.r() <= 0.0 && c.g() <= 0.0 && c.b() <= 0.0;
+
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, Some(4 | 6 | 7 | 9));
+        let reflective = matches!(self.illum, Some(3 | 5 | 8))
+            || (!is_black(self.ks) && is_black(self.kd) && self.map_kd.is_none());
+
+        if !is_black(self.ke) {
+            DiffuseLight::new(self.ke).into()
+        } else if transparent {
+            Dielectric::new(self.ni.unwrap_or(RI_GLASS)).into()
+        } else if reflective {
+            // Convert the Phong exponent into a roughness, so that Ns=0 is fully fuzzy and
+            // large exponents are close to a perfect mirror.
+            let fuzz = self.ns.map_or(0.0, |ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt());
+            Metal::new(self.ks, fuzz).into()
+        } else if let Some(tex) = self.map_kd {
+            Lambertian::from_texture(tex).into()
+        } else {
+            Lambertian::new(self.kd).into()
+        }
+    }
+}
+
+///
+/// Parses an MTL library, returning its materials by name. Texture paths are resolved relative to
+/// the directory containing the library.
+///
+pub(super) fn load_mtl(path: &Path) -> Result<HashMap<String, Material>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    parse_mtl(&contents, path)
+}
+
+fn parse_mtl(contents: &str, path: &Path) -> Result<HashMap<String, Material>> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (index, line) in contents.lines().enumerate() {
+        let loc = Location::new(path, index + 1);
+
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                bail!("{loc}: newmtl is missing a material name");
+            }
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl.into_material());
+            }
+            current = Some((args.join(" "), MtlMaterial::new()));
+            continue;
+        }
+
+        let Some((_, mtl)) = current.as_mut() else {
+            bail!("{loc}: `{keyword}` appears before any newmtl statement");
+        };
+
+        match keyword {
+            "Kd" => mtl.kd = parse_color(&args, &loc)?,
+            "Ks" => mtl.ks = parse_color(&args, &loc)?,
+            "Ke" => mtl.ke = parse_color(&args, &loc)?,
+            "Ni" => mtl.ni = Some(parse_scalar(&args, &loc)?),
+            "Ns" => mtl.ns = Some(parse_scalar(&args, &loc)?),
+            "d" => mtl.dissolve = parse_scalar(&args, &loc)?,
+            "Tr" => mtl.dissolve = 1.0 - parse_scalar(&args, &loc)?,
+            "illum" => {
+                let illum = parse_scalar(&args, &loc)?;
+                if illum < 0.0 || illum.fract() != 0.0 {
+                    bail!("{loc}: illum must be a non-negative integer");
+                }
+                mtl.illum = Some(illum as u32);
+            }
+            "map_Kd" => {
+                // Texture options come before the file name, which is always the last argument
+                let file = args
+                    .last()
+                    .ok_or_else(|| anyhow!("{loc}: map_Kd is missing a file name"))?;
+                let tex = Image::load(dir.join(file)).with_context(|| format!("{loc}"))?;
+                mtl.map_kd = Some(tex);
+            }
+            // Everything else has no equivalent in our materials
+            _ => {}
+        }
+    }
+
+    if let Some((name, mtl)) = current.take() {
+        materials.insert(name, mtl.into_material());
+    }
+
+    Ok(materials)
+}
+
+fn parse_color(args: &[&str], loc: &Location) -> Result<Color> {
+    if args.first() == Some(&"spectral") || args.first() == Some(&"xyz") {
+        bail!("{loc}: only RGB colors are supported");
+    }
+    let values = parse_floats(args, loc)?;
+    match values[..] {
+        // A single value is a grey color
+        [v] => Ok(Color::new(v, v, v)),
+        [r, g, b] => Ok(Color::new(r, g, b)),
+        _ => bail!(
+            "{loc}: expected 1 or 3 color components, found {}",
+            values.len()
+        ),
+    }
+}
+
+fn parse_scalar(args: &[&str], loc: &Location) -> Result<f64> {
+    // `d` may be written as `d -halo 0.5`, skip any options
+    let values = parse_floats(&args[args.len().saturating_sub(1)..], loc)?;
+    match values[..] {
+        [v] => Ok(v),
+        _ => bail!("{loc}: expected a value"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn materials_map_onto_the_closest_kind() {
+        let materials = parse_mtl(
+            "\
+newmtl matte
+Kd 0.5 0.2 0.1
+
+newmtl mirror
+Ks 0.9
+Kd 0
+Ns 1000
+
+newmtl glass
+d 0.5
+Ni 1.33
+
+newmtl lamp
+Ke 4 4 4
+illum 2
+",
+            Path::new("test.mtl"),
+        )
+        .unwrap();
+
+        assert!(matches!(materials["matte"], Material::Lambertian(_)));
+        assert!(matches!(materials["mirror"], Material::Metal(_)));
+        assert!(matches!(materials["glass"], Material::Dielectric(_)));
+        assert!(matches!(materials["lamp"], Material::DiffuseLight(_)));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |contents: &str| {
+            parse_mtl(contents, Path::new("test.mtl"))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("# no material yet\nKd 1 1 1"),
+            "test.mtl:2: `Kd` appears before any newmtl statement"
+        );
+        assert_eq!(
+            error("newmtl a\nKd 1 1\n"),
+            "test.mtl:2: expected 1 or 3 color components, found 2"
+        );
+        assert_eq!(
+            error("newmtl a\n\nillum 1.5"),
+            "test.mtl:3: illum must be a non-negative integer"
+        );
+    }
+}
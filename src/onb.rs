@@ -0,0 +1,64 @@
+use crate::vec3::Vec3;
+
+///
+/// An orthonormal basis, with its w axis along a given normal
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn new(n: Vec3) -> Self {
+        let w = n.unit_vector();
+
+        // Any vector not parallel to w will do to build the other two axes
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).unit_vector();
+        let u = w.cross(v);
+
+        Self { axis: [u, v, w] }
+    }
+
+    ///
+    /// A basis with its w axis along `n`, and its u axis as close to `tangent` as possible.
+    /// `tangent` must not be parallel to `n`.
+    ///
+    pub fn with_tangent(n: Vec3, tangent: Vec3) -> Self {
+        let w = n.unit_vector();
+        let u = (tangent - tangent.dot(w) * w).unit_vector();
+        let v = w.cross(u);
+
+        Self { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    ///
+    /// Transforms from basis coordinates to world coordinates
+    ///
+    pub fn transform(&self, v: Vec3) -> Vec3 {
+        v.x() * self.u() + v.y() * self.v() + v.z() * self.w()
+    }
+
+    ///
+    /// Transforms from world coordinates to basis coordinates
+    ///
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.u()), v.dot(self.v()), v.dot(self.w()))
+    }
+}
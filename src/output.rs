@@ -0,0 +1,216 @@
+//!
+//! Writing rendered images to disk. Low dynamic range formats get a gamma corrected 8-bit
+//! image, while the high dynamic range ones keep the framebuffer's linear colors as floats,
+//! including everything brighter than white.
+//!
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use exr::prelude::f16;
+use image::{codecs::hdr::HdrEncoder, ImageFormat, Rgb};
+use serde::{de::IntoDeserializer, Deserialize};
+
+use crate::{framebuffer::Framebuffer, tonemap::ToneMapping};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 8-bit image in any format the image crate can write, such as PNG or JPEG
+    Ldr(ImageFormat),
+
+    /// OpenEXR with 16-bit half float channels
+    ExrHalf,
+
+    /// OpenEXR with 32-bit float channels
+    ExrFloat,
+
+    /// Radiance RGBE
+    Hdr,
+
+    /// Portable float map, 32-bit float channels
+    Pfm,
+}
+
+impl OutputFormat {
+    ///
+    /// Picks the format from the extension of `path`. OpenEXR files are written with half floats.
+    ///
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+            .ok_or_else(|| anyhow!("Cannot tell the image format of {}", path.display()))
+    }
+
+    ///
+    /// The usual file extension for the format
+    ///
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Ldr(format) => format.extensions_str()[0],
+            OutputFormat::ExrHalf | OutputFormat::ExrFloat => "exr",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    ///
+    /// Parses a format name or file extension: exr (or exr-half), exr-float, hdr, pfm, or any
+    /// 8-bit format the image crate can write
+    ///
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "exr" | "exr-half" => Ok(OutputFormat::ExrHalf),
+            "exr-float" => Ok(OutputFormat::ExrFloat),
+            "hdr" => Ok(OutputFormat::Hdr),
+            "pfm" => Ok(OutputFormat::Pfm),
+            ext => match ImageFormat::from_extension(ext) {
+                Some(format) if format.can_write() => Ok(OutputFormat::Ldr(format)),
+                Some(_) => bail!("writing {s} images is not supported"),
+                None => bail!("unknown image format `{s}`"),
+            },
+        }
+    }
+}
+
+///
+/// How the two eyes of a stereo pair are stored, the left eye first
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoLayout {
+    /// One image, the left eye on the left half
+    SideBySide,
+
+    /// One image, the left eye on the top half, as VR players expect for panoramas
+    TopBottom,
+
+    /// Two images, with _left and _right added to the file name
+    Separate,
+}
+
+impl FromStr for StereoLayout {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+impl StereoLayout {
+    ///
+    /// The files a stereo pair is saved to, given the path a single image would be saved to
+    ///
+    pub fn paths(&self, path: &Path) -> Vec<PathBuf> {
+        match self {
+            StereoLayout::SideBySide | StereoLayout::TopBottom => vec![path.to_path_buf()],
+            StereoLayout::Separate => ["left", "right"]
+                .iter()
+                .map(|eye| {
+                    let mut name = path.file_stem().unwrap_or_default().to_owned();
+                    name.push(format!("_{eye}"));
+                    if let Some(extension) = path.extension() {
+                        name.push(".");
+                        name.push(extension);
+                    }
+                    path.with_file_name(name)
+                })
+                .collect(),
+        }
+    }
+
+    ///
+    /// Arranges the eyes into the images to save to each of `paths`
+    ///
+    pub fn arrange(&self, left: Framebuffer, right: Framebuffer) -> Vec<Framebuffer> {
+        match self {
+            StereoLayout::SideBySide => vec![left.beside(&right)],
+            StereoLayout::TopBottom => vec![left.above(&right)],
+            StereoLayout::Separate => vec![left, right],
+        }
+    }
+}
+
+///
+/// Writes `image` to `path` in `format`. Only the 8-bit formats are tone mapped, the others keep
+/// the linear colors.
+///
+pub fn save(
+    image: &Framebuffer,
+    path: impl AsRef<Path>,
+    format: OutputFormat,
+    tone_mapping: &ToneMapping,
+) -> Result<()> {
+    let path = path.as_ref();
+    let result = match format {
+        OutputFormat::Ldr(format) => image
+            .to_rgb8_with(tone_mapping)
+            .save_with_format(path, format)
+            .map_err(anyhow::Error::from),
+        OutputFormat::ExrHalf => write_exr(image, path, f16::from_f32),
+        OutputFormat::ExrFloat => write_exr(image, path, |c| c),
+        OutputFormat::Hdr => write_hdr(image, path),
+        OutputFormat::Pfm => write_pfm(image, path),
+    };
+    result.with_context(|| format!("Cannot write {}", path.display()))
+}
+
+fn write_exr<T: exr::prelude::IntoSample>(
+    image: &Framebuffer,
+    path: &Path,
+    sample: impl Fn(f32) -> T + Sync,
+) -> Result<()> {
+    exr::prelude::write_rgb_file(
+        path,
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| {
+            let c = image.pixel(x as u32, y as u32);
+            (
+                sample(c.r() as f32),
+                sample(c.g() as f32),
+                sample(c.b() as f32),
+            )
+        },
+    )?;
+    Ok(())
+}
+
+fn write_hdr(image: &Framebuffer, path: &Path) -> Result<()> {
+    let pixels: Vec<Rgb<f32>> = image
+        .pixels()
+        .iter()
+        .map(|c| Rgb([c.r() as f32, c.g() as f32, c.b() as f32]))
+        .collect();
+    let file = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(file).encode(&pixels, image.width() as usize, image.height() as usize)?;
+    Ok(())
+}
+
+///
+/// PFM stores rows from the bottom up, and a negative scale marks little endian floats
+///
+fn write_pfm(image: &Framebuffer, path: &Path) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for y in (0..image.height()).rev() {
+        for x in 0..image.width() {
+            let c = image.pixel(x, y);
+            for channel in [c.r(), c.g(), c.b()] {
+                file.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+//!
+//! Probability density functions over directions, used to importance sample scattered rays.
+//! Every PDF can both generate a random direction, and give the density of generating any
+//! given direction.
+//!
+
+use std::f64::consts::PI;
+
+use crate::{
+    hittable::hittable_list::HittableList,
+    material::{
+        dielectric::fresnel,
+        microfacet::{refract, refraction_half_vector, Ggx},
+    },
+    onb::Onb,
+    ray::Ray,
+    rng::random,
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Clone)]
+pub enum Pdf<'a> {
+    /// Uniform over every direction
+    Sphere,
+
+    /// Proportional to the cosine of the angle with the basis w axis
+    Cosine(Onb),
+
+    ///
+    /// Directions of `reflected + fuzz * random_unit_vector()`, the reflection lobe of a fuzzy
+    /// metal. `reflected` is a unit vector, and `fuzz` is in (0, 1].
+    ///
+    Fuzz { reflected: Vec3, fuzz: f64 },
+
+    ///
+    /// Reflections off the microfacet normals of `distribution` that `wo` sees, the lobe of a
+    /// rough conductor. `wo` is a unit vector pointing away from the surface, in the
+    /// coordinates of `frame`.
+    ///
+    Microfacet {
+        frame: Onb,
+        wo: Vec3,
+        distribution: Ggx,
+    },
+
+    ///
+    /// Reflections and refractions through the microfacet normals of `distribution` that `wo`
+    /// sees, chosen by their Fresnel reflectance, the lobes of frosted glass. `eta` is the
+    /// refractive index beyond the surface over the one on the side of `wo`.
+    ///
+    RoughDielectric {
+        frame: Onb,
+        wo: Vec3,
+        distribution: Ggx,
+        eta: f64,
+    },
+
+    /// Directions from `origin` towards a random point on one of `lights`, at time `time`
+    Lights {
+        lights: &'a HittableList,
+        origin: Point3,
+        time: f64,
+    },
+
+    /// Picks either PDF with equal probability
+    Mixture(Box<Pdf<'a>>, Box<Pdf<'a>>),
+}
+
+impl<'a> Pdf<'a> {
+    pub fn cosine(normal: Vec3) -> Self {
+        Pdf::Cosine(Onb::new(normal))
+    }
+
+    pub fn mixture(p0: Pdf<'a>, p1: Pdf<'a>) -> Self {
+        Pdf::Mixture(Box::new(p0), Box::new(p1))
+    }
+
+    ///
+    /// Density of generating `direction`, per unit solid angle
+    ///
+    pub fn value(&self, direction: Vec3) -> f64 {
+        match self {
+            Pdf::Sphere => 1.0 / (4.0 * PI),
+
+            Pdf::Cosine(uvw) => {
+                let cosine_theta = direction.unit_vector().dot(uvw.w());
+                f64::max(0.0, cosine_theta / PI)
+            }
+
+            Pdf::Fuzz { reflected, fuzz } => {
+                // Directions that pass through the sphere of radius fuzz around the reflected
+                // direction cross its surface twice, at distances t = b +- sqrt(disc). Summing
+                // the area density 1 / (4 pi fuzz^2) over both, converted to solid angle by
+                // t^2 / |cos|, where |cos| = sqrt(disc) / fuzz, gives this closed form.
+                let b = direction.unit_vector().dot(*reflected);
+                let disc = b * b - (1.0 - fuzz * fuzz);
+                if b <= 0.0 || disc <= 0.0 {
+                    return 0.0;
+                }
+                (b * b + disc) / (2.0 * PI * fuzz * disc.sqrt())
+            }
+
+            Pdf::Microfacet {
+                frame,
+                wo,
+                distribution,
+            } => {
+                // The density of the reflection is that of the normal halfway to it, divided by
+                // how much the reflection turns as that normal does
+                let wi = frame.to_local(direction.unit_vector());
+                let h = (*wo + wi).unit_vector();
+                let cos = wo.dot(h);
+                if cos <= 0.0 {
+                    return 0.0;
+                }
+                distribution.visible_d(*wo, h) / (4.0 * cos)
+            }
+
+            Pdf::RoughDielectric {
+                frame,
+                wo,
+                distribution,
+                eta,
+            } => {
+                // A rough surface can reflect below itself or refract back out, so either lobe
+                // can reach any direction
+                let wi = frame.to_local(direction.unit_vector());
+                let h = (*wo + wi).unit_vector();
+                let cos = wo.dot(h);
+                let reflection = if cos > 0.0 {
+                    fresnel(cos, *eta) * distribution.visible_d(*wo, h) / (4.0 * cos)
+                } else {
+                    0.0
+                };
+
+                let refraction = match refraction_half_vector(*wo, wi, *eta) {
+                    Some(h) => {
+                        let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+                        let jacobian = cos_i.abs() / (cos_i + cos_o / eta).powi(2);
+                        (1.0 - fresnel(cos_o, *eta)) * distribution.visible_d(*wo, h) * jacobian
+                    }
+                    None => 0.0,
+                };
+
+                reflection + refraction
+            }
+
+            Pdf::Lights {
+                lights,
+                origin,
+                time,
+            } => lights.pdf_value(Ray::with_time(*origin, direction, *time)),
+
+            Pdf::Mixture(p0, p1) => 0.5 * p0.value(direction) + 0.5 * p1.value(direction),
+        }
+    }
+
+    ///
+    /// Returns a random direction distributed according to this PDF. It is not necessarily a
+    /// unit vector.
+    ///
+    pub fn generate(&self) -> Vec3 {
+        match self {
+            Pdf::Sphere => Vec3::random_unit_vector(),
+
+            Pdf::Cosine(uvw) => uvw.transform(random_cosine_direction()),
+
+            Pdf::Fuzz { reflected, fuzz } => *reflected + *fuzz * Vec3::random_unit_vector(),
+
+            Pdf::Microfacet {
+                frame,
+                wo,
+                distribution,
+            } => {
+                let h = distribution.sample_visible_normal(*wo, random(), random());
+                frame.transform(2.0 * wo.dot(h) * h - *wo)
+            }
+
+            Pdf::RoughDielectric {
+                frame,
+                wo,
+                distribution,
+                eta,
+            } => {
+                let h = distribution.sample_visible_normal(*wo, random(), random());
+                let cos = wo.dot(h);
+                let refracted = match refract(*wo, h, *eta) {
+                    Some(wi) if random::<f64>() >= fresnel(cos, *eta) => Some(wi),
+                    _ => None,
+                };
+                frame.transform(refracted.unwrap_or(2.0 * cos * h - *wo))
+            }
+
+            Pdf::Lights {
+                lights,
+                origin,
+                time,
+            } => lights.random(*origin, *time),
+
+            Pdf::Mixture(p0, p1) => {
+                if random::<f64>() < 0.5 {
+                    p0.generate()
+                } else {
+                    p1.generate()
+                }
+            }
+        }
+    }
+}
+
+///
+/// Random direction around the z axis, with a density proportional to its z component
+///
+fn random_cosine_direction() -> Vec3 {
+    let r1 = random::<f64>();
+    let r2 = random::<f64>();
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+
+    Vec3::new(x, y, z)
+}
+
+///
+/// Random direction around the z axis, uniform over the cone that a sphere of `radius` at
+/// `distance_squared` from the origin covers
+///
+pub(crate) fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
+    let r1 = random::<f64>();
+    let r2 = random::<f64>();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+
+    Vec3::new(x, y, z)
+}
@@ -1,10 +1,15 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    color::Color,
+    spectrum::Wavelengths,
+    vec3::{Point3, Vec3},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
     time: f64,
+    wavelengths: Option<Wavelengths>, // Carried in spectral mode, None for RGB
 }
 
 impl Ray {
@@ -13,6 +18,7 @@ impl Ray {
             orig: origin,
             dir: direction,
             time: 0.0,
+            wavelengths: None,
         }
     }
 
@@ -21,9 +27,15 @@ impl Ray {
             orig: origin,
             dir: direction,
             time,
+            wavelengths: None,
         }
     }
 
+    pub fn with_wavelengths(mut self, wavelengths: Option<Wavelengths>) -> Self {
+        self.wavelengths = wavelengths;
+        self
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         self.orig + t * self.dir
     }
@@ -40,6 +52,20 @@ impl Ray {
         self.time
     }
 
+    pub fn wavelengths(&self) -> Option<Wavelengths> {
+        self.wavelengths
+    }
+
+    ///
+    /// `rgb` as the radiance at the wavelengths this ray carries, or unchanged in RGB mode
+    ///
+    pub fn uplift(&self, rgb: Color) -> Color {
+        match self.wavelengths {
+            Some(wavelengths) => wavelengths.uplift(rgb),
+            None => rgb,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn origin_mut(&mut self) -> &mut Point3 {
         &mut self.orig
@@ -0,0 +1,150 @@
+//!
+//! Seedable random numbers. Everything random draws from a generator local to its thread, which
+//! the camera restarts for every pixel sample on a stream derived from the global seed and the
+//! sample's position. A render is therefore the same for a given seed, however its pixels are
+//! spread across threads. Scenes with random layouts or textures restart it on a stream of
+//! their own before building. Noise textures draw from a `noise_generator` of their own, and
+//! draws made while searching for a hit use `keyed_random`.
+//!
+
+use std::{
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng, RngCore, SeedableRng,
+};
+use rand_pcg::Pcg64Mcg;
+
+static SEED: AtomicU64 = AtomicU64::new(0);
+
+// Key of the stream scenes are built from, kept apart from the camera's three part keys
+const SCENE_KEY: u64 = u64::MAX;
+
+// Key of the generators noise textures draw from, apart from every stream
+const NOISE_KEY: u64 = u64::MAX - 1;
+
+// A PCG rather than `SmallRng`, whose algorithm differs between platforms and rand versions, so
+// that a seed gives the same render everywhere
+thread_local! {
+    static RNG: RefCell<Pcg64Mcg> = RefCell::new(Pcg64Mcg::seed_from_u64(seed()));
+}
+
+// Seed of the stream last started on this thread, and how many searches for a hit it has started
+thread_local! {
+    static SEARCH: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+///
+/// Sets the seed every stream is derived from. Streams started afterwards use it.
+///
+pub fn set_seed(seed: u64) {
+    SEED.store(seed, Ordering::Relaxed);
+}
+
+pub fn seed() -> u64 {
+    SEED.load(Ordering::Relaxed)
+}
+
+///
+/// Restarts this thread's generator on the stream named by `key`. The same seed and key always
+/// give the same sequence of numbers.
+///
+pub fn start_stream(key: &[u64]) {
+    let seed = stream_seed(key);
+    RNG.with(|rng| *rng.borrow_mut() = Pcg64Mcg::seed_from_u64(seed));
+    SEARCH.with(|search| search.set((seed, 0)));
+}
+
+///
+/// Starts a new search of the world for a hit, which `keyed_random` draws for. Leaves this
+/// thread's generator alone, so it doesn't change the numbers drawn afterwards.
+///
+pub fn start_search() {
+    SEARCH.with(|search| {
+        let (seed, count) = search.get();
+        search.set((seed, count + 1));
+    });
+}
+
+///
+/// A random number in [0, 1) that depends only on the current search and `key`, leaving this
+/// thread's generator alone. For draws made during a search, which would otherwise depend on
+/// the order it visits objects in.
+///
+pub fn keyed_random(key: &[u64]) -> f64 {
+    let (seed, count) = SEARCH.with(Cell::get);
+    let h = key
+        .iter()
+        .fold(mix(seed ^ mix(count)), |h, k| mix(h ^ mix(*k)));
+    // The top 53 bits, as many as an f64 holds
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+///
+/// Restarts this thread's generator on the stream that scenes draw their layout from
+///
+pub fn start_scene_stream() {
+    start_stream(&[SCENE_KEY]);
+}
+
+///
+/// A generator of its own for the noise texture numbered `seed`. It depends on nothing else, not
+/// even the global seed, so that a scene file can name the noise of a built-in scene.
+///
+pub fn noise_generator(seed: u64) -> impl Rng {
+    Pcg64Mcg::seed_from_u64(mix(NOISE_KEY ^ mix(seed)))
+}
+
+///
+/// Handle to this thread's generator, usable anywhere a `rand::Rng` is
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadRng;
+
+pub fn thread_rng() -> ThreadRng {
+    ThreadRng
+}
+
+///
+/// A random value from this thread's generator, like `rand::random`
+///
+pub fn random<T>() -> T
+where
+    Standard: Distribution<T>,
+{
+    thread_rng().gen()
+}
+
+impl RngCore for ThreadRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+fn stream_seed(key: &[u64]) -> u64 {
+    key.iter().fold(mix(seed()), |h, k| mix(h ^ mix(*k)))
+}
+
+///
+/// SplitMix64's finalizer, which spreads every bit of `x` over the whole result
+///
+fn mix(x: u64) -> u64 {
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
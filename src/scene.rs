@@ -8,18 +8,28 @@ use rand::{
 };
 
 use crate::{
-    camera::Camera,
+    camera::{Camera, CameraKeyframe, ShutterCurve},
     color::Color,
     hittable::{
         bvh::BvhNode,
         constant_medium::ConstantMedium,
         hittable_list::HittableList,
         instance::{Translate, YRotate},
+        mesh::{Face, Mesh},
         quad::Quad,
         sphere::Sphere,
+        triangle::Triangle,
         Hittable,
     },
-    material::{dielectric::*, emissive::DiffuseLight, lambertian::Lambertian, metal::Metal},
+    material::{
+        conductor::{Conductor, ConductorPreset},
+        dielectric::*,
+        emissive::DiffuseLight,
+        lambertian::Lambertian,
+        metal::Metal,
+        thin_film::ThinFilm,
+    },
+    obj, rng,
     texture::{
         checker::Checker,
         image::Image,
@@ -28,6 +38,13 @@ use crate::{
     vec3::{Point3, Vec3},
 };
 
+// Path of a file in src/assets, which doesn't depend on where the renderer is run from
+macro_rules! asset {
+    ($name:literal) => {
+        concat!(env!("CARGO_MANIFEST_DIR"), "/src/assets/", $name)
+    };
+}
+
 // Worlds
 
 pub fn two_lambertians() -> Hittable {
@@ -105,6 +122,37 @@ pub fn fuzzed_metal() -> Hittable {
     world.into()
 }
 
+pub fn rough_metals() -> Hittable {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.0));
+    world.add(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    ));
+
+    // Polished silver, then rougher gold and copper, and aluminium brushed around Y
+    let metals = [
+        Conductor::preset(ConductorPreset::Silver, 0.0),
+        Conductor::preset(ConductorPreset::Gold, 0.2),
+        Conductor::preset(ConductorPreset::Copper, 0.4),
+        {
+            let (eta, k) = ConductorPreset::Aluminium.ior();
+            Conductor::anisotropic(eta, k, 0.1, 0.5)
+        },
+    ];
+    for (i, metal) in metals.into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(-1.5 + i as f64, 0.0, -1.2),
+            0.45,
+            metal,
+        ));
+    }
+
+    world.into()
+}
+
 pub fn solid_glass() -> Hittable {
     let mut world = HittableList::new();
 
@@ -137,6 +185,95 @@ pub fn solid_glass() -> Hittable {
     world.into()
 }
 
+pub fn frosted_glass() -> Hittable {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.0));
+    world.add(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    ));
+
+    // A checkered wall behind the spheres, to show how much each one blurs
+    let checker = Checker::from_colors(0.2, Color::new(0.1, 0.2, 0.5), Color::new(0.9, 0.9, 0.9));
+    world.add(Quad::new(
+        Point3::new(-3.0, -0.5, -2.5),
+        Vec3::new(6.0, 0.0, 0.0),
+        Vec3::new(0.0, 3.0, 0.0),
+        Lambertian::from_texture(checker),
+    ));
+
+    for (i, roughness) in [0.0, 0.1, 0.3].into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(-1.1 + 1.1 * i as f64, 0.0, -1.2),
+            0.5,
+            Dielectric::rough(RI_GLASS, roughness),
+        ));
+    }
+
+    world.into()
+}
+
+pub fn tinted_glass() -> Hittable {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.8));
+    world.add(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    ));
+
+    // The same green glass, looking darker the bigger the sphere
+    let glass = Dielectric::new(RI_GLASS).with_transmittance(Color::new(0.4, 0.8, 0.5), 0.5);
+    for (x, radius) in [(-1.2, 0.2), (-0.6, 0.3), (0.3, 0.5)] {
+        world.add(Sphere::new(
+            Point3::new(x, radius - 0.5, -1.2),
+            radius,
+            glass.clone(),
+        ));
+    }
+
+    world.into()
+}
+
+pub fn dispersive_glass() -> Hittable {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.8));
+    world.add(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    ));
+
+    // Black and white stripes behind the spheres, which dispersion fringes with color
+    let checker = Checker::from_colors(0.1, Color::black(), Color::new(0.9, 0.9, 0.9));
+    world.add(Quad::new(
+        Point3::new(-3.0, -0.5, -2.5),
+        Vec3::new(6.0, 0.0, 0.0),
+        Vec3::new(0.0, 3.0, 0.0),
+        Lambertian::from_texture(checker),
+    ));
+
+    // Crown glass, diamond, and a glass far more dispersive than any real one
+    let glasses = [
+        Dispersion::BK7,
+        Dispersion::DIAMOND,
+        Dispersion::Cauchy { a: 1.5, b: 0.05 },
+    ];
+    for (i, dispersion) in glasses.into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(-1.1 + 1.1 * i as f64, 0.0, -1.2),
+            0.5,
+            Dielectric::new(1.0).with_dispersion(dispersion),
+        ));
+    }
+
+    world.into()
+}
+
 pub fn air_bubble() -> Hittable {
     let mut world = HittableList::new();
 
@@ -207,6 +344,50 @@ pub fn hollow_glass() -> Hittable {
     world.into()
 }
 
+pub fn iridescence() -> Hittable {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+    world.add(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    ));
+
+    // A dark wall behind, against which the faint colors of the films stand out
+    world.add(Quad::new(
+        Point3::new(-3.0, -0.5, -2.5),
+        Vec3::new(6.0, 0.0, 0.0),
+        Vec3::new(0.0, 3.0, 0.0),
+        Lambertian::new(Color::new(0.02, 0.02, 0.02)),
+    ));
+
+    // A soap bubble, its film swirling between clear and 800 nm thick
+    let soap = ThinFilm::textured(RI_WATER, Noise::new(4.0).into(), 800.0);
+    world.add(Sphere::new(
+        Point3::new(-1.1, 0.0, -1.2),
+        0.5,
+        Dielectric::new(RI_AIR).with_film(soap),
+    ));
+
+    // Anodized aluminium, with an uneven layer of oxide up to 400 nm thick
+    let oxide = ThinFilm::textured(2.4, Noise::new(3.0).with_seed(1).into(), 400.0);
+    world.add(Sphere::new(
+        Point3::new(0.0, 0.0, -1.2),
+        0.5,
+        Conductor::preset(ConductorPreset::Aluminium, 0.1).with_film(oxide),
+    ));
+
+    // Glass with an even coating, which shifts color towards the edges
+    world.add(Sphere::new(
+        Point3::new(1.1, 0.0, -1.2),
+        0.5,
+        Dielectric::new(RI_GLASS).with_film(ThinFilm::new(2.0, 150.0)),
+    ));
+
+    world.into()
+}
+
 pub fn two_spheres() -> Hittable {
     let mut world = HittableList::new();
 
@@ -221,6 +402,8 @@ pub fn two_spheres() -> Hittable {
 }
 
 pub fn random_spheres() -> Hittable {
+    rng::start_scene_stream();
+
     let mut world = HittableList::new();
 
     let ground_material = Lambertian::new(Color::half_grey());
@@ -233,7 +416,7 @@ pub fn random_spheres() -> Hittable {
     const BALL_RADIUS: f64 = 0.2;
 
     // Useful values used throughout the loops
-    let mut rng = rand::thread_rng();
+    let mut rng = rng::thread_rng();
     let ball_dist_center = Point3::new(4.0, BALL_RADIUS, 0.0);
     let metal_color_dist = Uniform::from(0.5..1.0);
     let metal_fuzz_dist = Uniform::from(0.0..0.5);
@@ -284,10 +467,12 @@ pub fn random_spheres() -> Hittable {
     let mat3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
     world.add(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3));
 
-    world.into()
+    BvhNode::from_list(world).into()
 }
 
 pub fn bouncing_random_spheres() -> Hittable {
+    rng::start_scene_stream();
+
     let mut world = HittableList::new();
 
     let ground_material = Lambertian::new(Color::half_grey());
@@ -300,7 +485,7 @@ pub fn bouncing_random_spheres() -> Hittable {
     const BALL_RADIUS: f64 = 0.2;
 
     // Useful values used throughout the loops
-    let mut rng = rand::thread_rng();
+    let mut rng = rng::thread_rng();
     let ball_dist_center = Point3::new(4.0, BALL_RADIUS, 0.0);
     let metal_color_dist = Uniform::from(0.5..1.0);
     let metal_fuzz_dist = Uniform::from(0.0..0.5);
@@ -360,6 +545,8 @@ pub fn bouncing_random_spheres() -> Hittable {
 }
 
 pub fn bouncing_random_spheres_checkerboard() -> Hittable {
+    rng::start_scene_stream();
+
     let mut world = HittableList::new();
 
     let checker = Checker::from_colors(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
@@ -373,7 +560,7 @@ pub fn bouncing_random_spheres_checkerboard() -> Hittable {
     const BALL_RADIUS: f64 = 0.2;
 
     // Useful values used throughout the loops
-    let mut rng = rand::thread_rng();
+    let mut rng = rng::thread_rng();
     let ball_dist_center = Point3::new(4.0, BALL_RADIUS, 0.0);
     let metal_color_dist = Uniform::from(0.5..1.0);
     let metal_fuzz_dist = Uniform::from(0.0..0.5);
@@ -452,7 +639,7 @@ pub fn two_checkered_spheres() -> Hittable {
 }
 
 pub fn earth() -> Hittable {
-    let earth_texture = Image::new("src/assets/earthmap.jpg");
+    let earth_texture = Image::new(asset!("earthmap.jpg"));
     let earth_surface = Lambertian::from_texture(earth_texture);
     let globe = Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0, earth_surface);
 
@@ -549,6 +736,130 @@ pub fn quads() -> Hittable {
     world.into()
 }
 
+pub fn triangles() -> Hittable {
+    let mut world = HittableList::new();
+
+    // Materials
+    let left_red = Lambertian::new(Color::new(1.0, 0.2, 0.2));
+    let right_blue = Lambertian::new(Color::new(0.2, 0.2, 1.0));
+    let checker = Checker::from_colors(0.5, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+
+    // Standalone triangles
+    world.add(Triangle::new(
+        Point3::new(-3.0, -2.0, 5.0),
+        Point3::new(-3.0, -2.0, 1.0),
+        Point3::new(-3.0, 2.0, 3.0),
+        left_red,
+    ));
+    world.add(Triangle::with_attributes(
+        [
+            Point3::new(3.0, -2.0, 1.0),
+            Point3::new(3.0, -2.0, 5.0),
+            Point3::new(3.0, 2.0, 3.0),
+        ],
+        [
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(-1.0, 0.0, 1.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ],
+        [(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)],
+        right_blue,
+    ));
+
+    // Square pyramid sharing its vertices between faces
+    let positions = vec![
+        Point3::new(-2.0, -2.0, 0.0),
+        Point3::new(2.0, -2.0, 0.0),
+        Point3::new(2.0, -2.0, 4.0),
+        Point3::new(-2.0, -2.0, 4.0),
+        Point3::new(0.0, 2.0, 2.0),
+    ];
+    let indices = vec![
+        [0, 4, 1],
+        [1, 4, 2],
+        [2, 4, 3],
+        [3, 4, 0],
+        [0, 1, 2],
+        [0, 2, 3],
+    ];
+    world.add(
+        Mesh::from_indexed(positions, indices, Lambertian::from_texture(checker))
+            .expect("Pyramid indices are in range"),
+    );
+
+    world.into()
+}
+
+pub fn earth_mesh() -> Hittable {
+    const RADIUS: f64 = 2.0;
+    const SLICES: usize = 64; // Divisions around the Y axis
+    const STACKS: usize = 32; // Divisions from Y=-1 to Y=+1
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+
+    // Lay the vertices out with the same (u,v) parameterization as a Sphere, so that the same
+    // image texture maps identically onto both.
+    for j in 0..=STACKS {
+        let v = j as f64 / STACKS as f64;
+        let theta = v * PI;
+        for i in 0..=SLICES {
+            let u = i as f64 / SLICES as f64;
+            let phi = u * 2.0 * PI;
+            let n = Vec3::new(
+                -phi.cos() * theta.sin(),
+                -theta.cos(),
+                phi.sin() * theta.sin(),
+            );
+
+            positions.push(RADIUS * n);
+            normals.push(n);
+            uvs.push((u, v));
+        }
+    }
+
+    let mut faces = Vec::new();
+    let row = SLICES + 1;
+    for j in 0..STACKS {
+        for i in 0..SLICES {
+            let a = j * row + i;
+            let b = a + 1;
+            let c = a + row;
+            let d = c + 1;
+            for vertices in [[a, b, c], [b, d, c]] {
+                faces.push(Face {
+                    vertices,
+                    normals: Some(vertices),
+                    uvs: Some(vertices),
+                    material: 0,
+                });
+            }
+        }
+    }
+
+    let earth_surface = Lambertian::from_texture(Image::new(asset!("earthmap.jpg")));
+
+    Mesh::new(positions, normals, uvs, faces, vec![earth_surface.into()])
+        .expect("Sphere indices are in range")
+        .into()
+}
+
+pub fn icosahedron() -> Hittable {
+    let mut world = HittableList::new();
+
+    let checker = Checker::from_colors(0.5, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0.0, -1001.5, 0.0),
+        1000.0,
+        Lambertian::from_texture(checker),
+    ));
+
+    world.add(obj::load(asset!("icosahedron.obj")).expect("Cannot load icosahedron model"));
+
+    world.into()
+}
+
 pub fn symbol() -> Hittable {
     let mut world = HittableList::new();
 
@@ -564,20 +875,8 @@ pub fn symbol() -> Hittable {
         Lambertian::from_texture(per_text),
     ));
 
-    let diff_light = DiffuseLight::new(Color::new(0.0, 2.0, 8.0));
-    world.add(Quad::new(
-        Point3::new(3.0, 10.0, 3.0),
-        Vec3::new(-6.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -6.0),
-        diff_light,
-    ));
-
-    let sphere_light = DiffuseLight::new(Color::new(4.0, 4.0, 4.0));
-    world.add(Sphere::new(
-        Point3::new(-5.0, 2.0, -15.0 / 13.0),
-        2.5,
-        sphere_light,
-    ));
+    world.add(symbol_quad_light());
+    world.add(symbol_sphere_light());
 
     world.into()
 }
@@ -588,7 +887,6 @@ pub fn cornell_box() -> Hittable {
     let red = Lambertian::new(Color::new(0.65, 0.05, 0.05));
     let white = Lambertian::new(Color::new(0.73, 0.73, 0.73));
     let green = Lambertian::new(Color::new(0.12, 0.45, 0.15));
-    let light = DiffuseLight::new(Color::new(15.0, 15.0, 15.0));
 
     world.add(Quad::new(
         Point3::new(555.0, 0.0, 0.0),
@@ -602,12 +900,7 @@ pub fn cornell_box() -> Hittable {
         Vec3::new(0.0, 0.0, 555.0),
         red,
     ));
-    world.add(Quad::new(
-        Point3::new(343.0, 554.0, 332.0),
-        Vec3::new(-130.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -105.0),
-        light,
-    ));
+    world.add(cornell_box_light());
     world.add(Quad::new(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
@@ -654,7 +947,6 @@ pub fn cornell_smoke() -> Hittable {
     let red = Lambertian::new(Color::new(0.65, 0.05, 0.05));
     let white = Lambertian::new(Color::new(0.73, 0.73, 0.73));
     let green = Lambertian::new(Color::new(0.12, 0.45, 0.15));
-    let light = DiffuseLight::new(Color::new(15.0, 15.0, 15.0));
 
     world.add(Quad::new(
         Point3::new(555.0, 0.0, 0.0),
@@ -668,12 +960,7 @@ pub fn cornell_smoke() -> Hittable {
         Vec3::new(0.0, 0.0, 555.0),
         red,
     ));
-    world.add(Quad::new(
-        Point3::new(113.0, 554.0, 127.0),
-        Vec3::new(330.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 305.0),
-        light,
-    ));
+    world.add(cornell_smoke_light());
     world.add(Quad::new(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
@@ -715,12 +1002,14 @@ pub fn cornell_smoke() -> Hittable {
 }
 
 pub fn book2_final() -> Hittable {
+    rng::start_scene_stream();
+
     let mut boxes1 = HittableList::new();
     let ground = Lambertian::new(Color::new(0.48, 0.83, 0.53));
 
     const BOXES_PER_SIDE: usize = 20;
     let height_dist = Uniform::from(1.0..=101.0);
-    let mut rng = rand::thread_rng();
+    let mut rng = rng::thread_rng();
     for i in 0..BOXES_PER_SIDE {
         for j in 0..BOXES_PER_SIDE {
             const W: f64 = 100.0;
@@ -743,13 +1032,7 @@ pub fn book2_final() -> Hittable {
 
     world.add(BvhNode::from_list(boxes1));
 
-    let light = DiffuseLight::new(7.0 * Color::white());
-    world.add(Quad::new(
-        Point3::new(123.0, 554.0, 147.0),
-        Vec3::new(300.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 256.0),
-        light,
-    ));
+    world.add(book2_final_light());
 
     let center0 = Point3::new(400.0, 400.0, 200.0);
     let center1 = center0 + Vec3::new(30.0, 0.0, 0.0);
@@ -785,7 +1068,7 @@ pub fn book2_final() -> Hittable {
     );
     world.add(ConstantMedium::new(boundary, 0.0001, Color::white()));
 
-    let earth_mat = Lambertian::from_texture(Image::new("src/assets/earthmap.jpg"));
+    let earth_mat = Lambertian::from_texture(Image::new(asset!("earthmap.jpg")));
     world.add(Sphere::new(
         Point3::new(400.0, 200.0, 400.0),
         100.0,
@@ -815,7 +1098,82 @@ pub fn book2_final() -> Hittable {
         Vec3::new(-100.0, 270.0, 395.0),
     ));
 
-    world.into()
+    BvhNode::from_list(world).into()
+}
+
+// Lights, shared between the worlds and the lists of lights to sample directly
+
+fn symbol_quad_light() -> Quad {
+    Quad::new(
+        Point3::new(3.0, 10.0, 3.0),
+        Vec3::new(-6.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -6.0),
+        DiffuseLight::new(Color::new(0.0, 2.0, 8.0)),
+    )
+}
+
+fn symbol_sphere_light() -> Sphere {
+    Sphere::new(
+        Point3::new(-5.0, 2.0, -15.0 / 13.0),
+        2.5,
+        DiffuseLight::new(Color::new(4.0, 4.0, 4.0)),
+    )
+}
+
+fn cornell_box_light() -> Quad {
+    Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        DiffuseLight::new(Color::new(15.0, 15.0, 15.0)),
+    )
+}
+
+fn cornell_smoke_light() -> Quad {
+    Quad::new(
+        Point3::new(113.0, 554.0, 127.0),
+        Vec3::new(330.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 305.0),
+        DiffuseLight::new(Color::new(15.0, 15.0, 15.0)),
+    )
+}
+
+fn book2_final_light() -> Quad {
+    Quad::new(
+        Point3::new(123.0, 554.0, 147.0),
+        Vec3::new(300.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 256.0),
+        DiffuseLight::new(7.0 * Color::white()),
+    )
+}
+
+pub fn no_lights() -> HittableList {
+    HittableList::new()
+}
+
+pub fn symbol_lights() -> HittableList {
+    let mut lights = HittableList::new();
+    lights.add(symbol_quad_light());
+    lights.add(symbol_sphere_light());
+    lights
+}
+
+pub fn cornell_box_lights() -> HittableList {
+    let mut lights = HittableList::new();
+    lights.add(cornell_box_light());
+    lights
+}
+
+pub fn cornell_smoke_lights() -> HittableList {
+    let mut lights = HittableList::new();
+    lights.add(cornell_smoke_light());
+    lights
+}
+
+pub fn book2_final_lights() -> HittableList {
+    let mut lights = HittableList::new();
+    lights.add(book2_final_light());
+    lights
 }
 
 // Camera positions and layouts
@@ -836,6 +1194,15 @@ pub fn unmoved_camera() -> Camera {
         focus_dist: 1.0,
 
         background: None,
+
+        ..Camera::new_basic()
+    }
+}
+
+pub fn spectral_camera() -> Camera {
+    Camera {
+        spectral: true,
+        ..unmoved_camera()
     }
 }
 
@@ -855,6 +1222,8 @@ pub fn far_camera() -> Camera {
         focus_dist: 2.0 * (3_f64).sqrt(),
 
         background: None,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -874,6 +1243,8 @@ pub fn far_camera_zoomed() -> Camera {
         focus_dist: 2.0 * (3_f64).sqrt(),
 
         background: None,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -893,6 +1264,8 @@ pub fn far_camera_zoomed_large_aperture() -> Camera {
         focus_dist: 3.4,
 
         background: None,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -912,6 +1285,8 @@ pub fn random_spheres_camera() -> Camera {
         focus_dist: 10.0,
 
         background: None,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -931,9 +1306,53 @@ pub fn random_spheres_camera_fast() -> Camera {
         focus_dist: 10.0,
 
         background: None,
+
+        ..Camera::new_basic()
+    }
+}
+
+///
+/// random_spheres_camera sliding sideways and turning while the shutter is open
+///
+pub fn random_spheres_camera_moving() -> Camera {
+    let camera = random_spheres_camera_fast();
+    Camera {
+        motion: vec![
+            camera.keyframe(0.0),
+            CameraKeyframe {
+                look_from: Point3::new(13.0, 2.0, 2.4),
+                look_at: Point3::new(0.0, 0.0, 0.3),
+                ..camera.keyframe(1.0)
+            },
+        ],
+        shutter_curve: ShutterCurve::Triangle,
+        ..camera
     }
 }
 
+///
+/// Circles a quarter turn around the random spheres from time 0 to 1, closing in and widening
+/// its view, keeping the center in focus. Meant for animations, see --frames.
+///
+pub fn random_spheres_camera_flythrough() -> Camera {
+    let camera = random_spheres_camera_fast();
+    let motion = (0..=8)
+        .map(|i| {
+            let t = i as f64 / 8.0;
+            let angle = (13.0 + 90.0 * t).to_radians();
+            let radius = 13.3 - 4.0 * t;
+            let look_from = Point3::new(radius * angle.cos(), 2.0 - 0.8 * t, radius * angle.sin());
+            CameraKeyframe {
+                look_from,
+                vfov: 20.0 + 10.0 * t,
+                focus_dist: (look_from - camera.look_at).length(),
+                ..camera.keyframe(t)
+            }
+        })
+        .collect();
+    Camera { motion, ..camera }
+}
+
 pub fn two_spheres_camera() -> Camera {
     Camera {
         aspect_ratio: 16.0 / 9.0,
@@ -950,6 +1369,8 @@ pub fn two_spheres_camera() -> Camera {
         focus_dist: 10.0,
 
         background: None,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -969,6 +1390,8 @@ pub fn earth_camera() -> Camera {
         focus_dist: 10.0,
 
         background: None,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -988,6 +1411,29 @@ pub fn quads_camera() -> Camera {
         focus_dist: 10.0,
 
         background: None,
+
+        ..Camera::new_basic()
+    }
+}
+
+pub fn icosahedron_camera() -> Camera {
+    Camera {
+        aspect_ratio: 16.0 / 9.0,
+        image_width: 480,
+        samples_per_pixel: 100,
+        max_depth: 50,
+
+        vfov: 20.0,
+        look_from: Point3::new(4.0, 5.0, 10.0),
+        look_at: Point3::new(0.0, 0.0, 0.0),
+        v_up: Vec3::new(0.0, 1.0, 0.0),
+
+        defocus_angle: 0.0,
+        focus_dist: 10.0,
+
+        background: None,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -1007,6 +1453,8 @@ pub fn symbol_camera() -> Camera {
 
         defocus_angle: 0.0,
         focus_dist: 10.0,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -1025,6 +1473,8 @@ pub fn cornell_box_cam() -> Camera {
 
         defocus_angle: 0.0,
         focus_dist: 10.0,
+
+        ..Camera::new_basic()
     }
 }
 
@@ -1043,5 +1493,115 @@ pub fn book2_final_camera(image_width: u32, samples_per_pixel: u32, max_depth: u
 
         defocus_angle: 0.0,
         focus_dist: 10.0,
+
+        ..Camera::new_basic()
     }
 }
+
+// Registry of the scenes and cameras above, for selecting them by name
+
+pub struct BuiltinScene {
+    pub name: &'static str,
+    pub world: fn() -> Hittable,
+    pub lights: fn() -> HittableList, // Lights of the world to sample directly
+    pub camera: BuiltinCamera,        // Camera the scene was laid out for
+}
+
+pub struct BuiltinCamera {
+    pub name: &'static str,
+    pub camera: fn() -> Camera,
+}
+
+macro_rules! builtin_camera {
+    ($camera:ident) => {
+        BuiltinCamera {
+            name: stringify!($camera),
+            camera: $camera,
+        }
+    };
+}
+
+macro_rules! builtin_scene {
+    ($world:ident, $camera:ident) => {
+        builtin_scene!($world, $camera, no_lights)
+    };
+    ($world:ident, $camera:ident, $lights:ident) => {
+        BuiltinScene {
+            name: stringify!($world),
+            world: $world,
+            lights: $lights,
+            camera: builtin_camera!($camera),
+        }
+    };
+}
+
+const BOOK2_FINAL_CAMERA: BuiltinCamera = BuiltinCamera {
+    name: "book2_final_camera",
+    camera: || book2_final_camera(400, 250, 4),
+};
+
+pub static SCENES: &[BuiltinScene] = &[
+    builtin_scene!(two_lambertians, unmoved_camera),
+    builtin_scene!(smooth_metal, unmoved_camera),
+    builtin_scene!(fuzzed_metal, unmoved_camera),
+    builtin_scene!(rough_metals, unmoved_camera),
+    builtin_scene!(solid_glass, unmoved_camera),
+    builtin_scene!(frosted_glass, unmoved_camera),
+    builtin_scene!(tinted_glass, unmoved_camera),
+    builtin_scene!(dispersive_glass, spectral_camera),
+    builtin_scene!(air_bubble, unmoved_camera),
+    builtin_scene!(hollow_glass, far_camera_zoomed_large_aperture),
+    builtin_scene!(iridescence, unmoved_camera),
+    builtin_scene!(two_spheres, unmoved_camera),
+    builtin_scene!(random_spheres, random_spheres_camera),
+    builtin_scene!(bouncing_random_spheres, random_spheres_camera_fast),
+    builtin_scene!(
+        bouncing_random_spheres_checkerboard,
+        random_spheres_camera_fast
+    ),
+    builtin_scene!(two_checkered_spheres, two_spheres_camera),
+    builtin_scene!(earth, earth_camera),
+    builtin_scene!(perlin_spheres, two_spheres_camera),
+    builtin_scene!(turbulent_spheres, two_spheres_camera),
+    builtin_scene!(marble_spheres, two_spheres_camera),
+    builtin_scene!(quads, quads_camera),
+    builtin_scene!(triangles, quads_camera),
+    builtin_scene!(earth_mesh, earth_camera),
+    builtin_scene!(icosahedron, icosahedron_camera),
+    builtin_scene!(symbol, symbol_camera, symbol_lights),
+    builtin_scene!(cornell_box, cornell_box_cam, cornell_box_lights),
+    builtin_scene!(cornell_smoke, cornell_box_cam, cornell_smoke_lights),
+    BuiltinScene {
+        name: "book2_final",
+        world: book2_final,
+        lights: book2_final_lights,
+        camera: BOOK2_FINAL_CAMERA,
+    },
+];
+
+pub static CAMERAS: &[BuiltinCamera] = &[
+    builtin_camera!(unmoved_camera),
+    builtin_camera!(spectral_camera),
+    builtin_camera!(far_camera),
+    builtin_camera!(far_camera_zoomed),
+    builtin_camera!(far_camera_zoomed_large_aperture),
+    builtin_camera!(random_spheres_camera),
+    builtin_camera!(random_spheres_camera_fast),
+    builtin_camera!(random_spheres_camera_moving),
+    builtin_camera!(random_spheres_camera_flythrough),
+    builtin_camera!(two_spheres_camera),
+    builtin_camera!(earth_camera),
+    builtin_camera!(quads_camera),
+    builtin_camera!(icosahedron_camera),
+    builtin_camera!(symbol_camera),
+    builtin_camera!(cornell_box_cam),
+    BOOK2_FINAL_CAMERA,
+];
+
+pub fn find_scene(name: &str) -> Option<&'static BuiltinScene> {
+    SCENES.iter().find(|s| s.name == name)
+}
+
+pub fn find_camera(name: &str) -> Option<&'static BuiltinCamera> {
+    CAMERAS.iter().find(|c| c.name == name)
+}
@@ -0,0 +1,1100 @@
+//!
+//! Loads scenes from TOML description files. A scene file has one `[camera]` table, named
+//! `[textures.<name>]`, `[materials.<name>]` and `[groups.<name>]` tables, and a list of
+//! `[[objects]]`. Objects and groups are added to the world unless they name a parent `group`,
+//! and can be placed with an invertible 4x4 `matrix` (by rows, the last one `[0, 0, 0, 1]`), then
+//! `scale` (one factor or one per axis), `rotate_x`, `rotate_y` and `rotate_z` (degrees around each
+//! axis), then `translate`.
+//! They can also move over the ray time with a list of `keyframes`, each with a `time` and an
+//! optional `scale`, rotation by `angle` degrees around `axis` (Y by default), and `translate`,
+//! applied after the placement above. A constant medium's placement moves its boundary, so that
+//! its density stays per unit length of the world.
+//! The camera can move the same way, with `[[camera.motion]]` keyframes of `look_from`,
+//! `look_at`, `v_up`, `vfov`, `focus_dist` and `defocus_angle`.
+//! Its defocus blur takes the shape of an aperture of `aperture_blades` blades turned
+//! `aperture_rotation` degrees, or of the brightness of the texture named by `aperture_texture`.
+//! An `[animation]` table makes the scene an image sequence of `frames` frames, over ray times
+//! `start_time` to `end_time` with a `shutter_angle` in degrees.
+//! Objects outside of any group, other than constant media, can be marked `light = true`, to have
+//! the camera sample them directly.
+//! Noise textures draw the noise numbered by their `seed`, 0 by default, as their counterparts in
+//! the built-in scenes do.
+//! File paths are relative to the directory containing the scene file.
+//!
+//! See the `scenes` directory for examples, which reproduce the scenes from `scene.rs`.
+//!
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    animation::Animation,
+    aperture::Aperture,
+    camera::{
+        Camera, CameraKeyframe, MisHeuristic, Projection, Sampling, ShutterCurve, StereoMode,
+    },
+    color::Color,
+    filter::Filter,
+    hittable::{
+        bvh::{BvhNode, BvhSplit},
+        constant_medium::ConstantMedium,
+        hittable_list::HittableList,
+        instance::{Keyframe, KeyframedTransform, Transform, Translate, YRotate},
+        quad::Quad,
+        sphere::Sphere,
+        triangle::Triangle,
+        Hittable,
+    },
+    material::{
+        conductor::{Conductor, ConductorPreset},
+        dielectric::{Dielectric, Dispersion},
+        emissive::DiffuseLight,
+        isotropic::Isotropic,
+        lambertian::Lambertian,
+        metal::Metal,
+        thin_film::ThinFilm,
+        Material,
+    },
+    matrix::Matrix4,
+    obj,
+    texture::{
+        checker::Checker,
+        image::Image,
+        noise::{MarbleNoise, Noise, TurbNoise},
+        solid_color::SolidColor,
+        Texture,
+    },
+    vec3::Vec3,
+};
+
+pub struct Scene {
+    pub camera: Camera,
+    pub world: Hittable,
+    pub lights: HittableList,
+    pub animation: Option<Animation>, // Image sequence the scene is meant to be rendered as
+}
+
+///
+/// Reads and builds the scene described by the TOML file at `path`.
+///
+pub fn load(path: impl AsRef<Path>) -> Result<Scene> {
+    let path = path.as_ref();
+    let source =
+        fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    let desc: SceneDesc =
+        toml::from_str(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+
+    let mut builder = Builder {
+        path,
+        dir: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        source: &source,
+        desc: &desc,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+    };
+
+    let camera = builder.camera()?;
+    let animation = builder.animation()?;
+    let (world, lights) = builder.world()?;
+
+    Ok(Scene {
+        camera,
+        world,
+        lights,
+        animation,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: CameraDesc,
+    animation: Option<AnimationDesc>,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    groups: HashMap<String, Spanned<GroupDesc>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+}
+
+///
+/// Any field that is left out takes its value from `Camera::new_basic`.
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    aspect_ratio: Option<f64>,
+    image_width: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<u32>,
+    vfov: Option<f64>,
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    v_up: Option<[f64; 3]>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    background: Option<[f64; 3]>,
+    sampling: Option<Sampling>,
+    heuristic: Option<MisHeuristic>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+    shutter_curve: Option<ShutterCurveDesc>,
+    #[serde(default)]
+    motion: Vec<CameraKeyframeDesc>,
+    projection: Option<Projection>,
+    view_width: Option<f64>,
+    fisheye_fov: Option<f64>,
+    ipd: Option<f64>,
+    convergence_dist: Option<f64>,
+    stereo_mode: Option<StereoMode>,
+    aperture_blades: Option<u32>,
+    aperture_rotation: Option<f64>,
+    aperture_texture: Option<String>,
+    anamorphic_squeeze: Option<f64>,
+    vignetting: Option<f64>,
+    filter: Option<Filter>,
+    filter_radius: Option<f64>,
+    spectral: Option<bool>,
+}
+
+///
+/// Fields left out take their value from `Animation::new`
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnimationDesc {
+    frames: u32,
+    start_time: Option<f64>,
+    end_time: Option<f64>,
+    shutter_angle: Option<f64>,
+}
+
+///
+/// A named shutter curve, or the weights of a custom one
+///
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ShutterCurveDesc {
+    Named(String),
+    Weights(Vec<f64>),
+}
+
+///
+/// Fields left out take the camera's own value
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraKeyframeDesc {
+    time: f64,
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    v_up: Option<[f64; 3]>,
+    vfov: Option<f64>,
+    focus_dist: Option<f64>,
+    defocus_angle: Option<f64>,
+}
+
+///
+/// Either an inline RGB color, or the name of a texture.
+///
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64; 3]),
+    Named(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        scale: f64,
+        even: TextureRef,
+        odd: TextureRef,
+    },
+    Image {
+        path: PathBuf,
+    },
+    Noise {
+        scale: f64,
+        #[serde(default)]
+        seed: u64,
+    },
+    Turbulence {
+        scale: f64,
+        depth: u32,
+        #[serde(default)]
+        seed: u64,
+    },
+    Marble {
+        scale: f64,
+        depth: u32,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureRef,
+    },
+    Metal {
+        albedo: [f64; 3],
+        fuzz: f64,
+    },
+    ///
+    /// A rough metal, either a `preset` or one with the complex refractive index `eta + i k`.
+    /// `roughness_u` and `roughness_v` override `roughness` along and across the tangent around Y.
+    ///
+    Conductor {
+        preset: Option<ConductorPreset>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+        roughness_u: Option<f64>,
+        roughness_v: Option<f64>,
+        film: Option<FilmDesc>,
+    },
+    ///
+    /// Refracts by exactly one of a fixed `refraction_index`, or the `cauchy` or `sellmeier`
+    /// coefficients of a dispersive one. Tinted by either its `absorption` per unit distance, or
+    /// the `transmittance` left after `transmittance_distance` (1 by default)
+    ///
+    Dielectric {
+        refraction_index: Option<f64>,
+        cauchy: Option<[f64; 2]>,
+        sellmeier: Option<SellmeierDesc>,
+        #[serde(default)]
+        roughness: f64,
+        absorption: Option<[f64; 3]>,
+        transmittance: Option<[f64; 3]>,
+        transmittance_distance: Option<f64>,
+        film: Option<FilmDesc>,
+    },
+    DiffuseLight {
+        emit: TextureRef,
+    },
+    Isotropic {
+        albedo: TextureRef,
+    },
+}
+
+///
+/// A thin-film coating `thickness` nanometers thick, or up to that thick where
+/// `thickness_texture` is white
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilmDesc {
+    refraction_index: f64,
+    thickness: f64,
+    thickness_texture: Option<TextureRef>,
+}
+
+///
+/// The three terms of the Sellmeier equation, for wavelengths in micrometers
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDesc {
+    b: [f64; 3],
+    c: [f64; 3],
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum GroupKind {
+    #[default]
+    List,
+    Bvh,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupDesc {
+    #[serde(default)]
+    kind: GroupKind,
+    // How a bvh group divides its objects, at the median by default
+    split: Option<BvhSplit>,
+    group: Option<String>,
+    matrix: Option<[[f64; 4]; 4]>,
+    scale: Option<ScaleDesc>,
+    rotate_x: Option<f64>,
+    rotate_y: Option<f64>,
+    rotate_z: Option<f64>,
+    translate: Option<[f64; 3]>,
+    #[serde(default)]
+    keyframes: Vec<KeyframeDesc>,
+}
+
+#[derive(Deserialize)]
+struct ObjectDesc {
+    group: Option<String>,
+    #[serde(default)]
+    light: bool,
+    matrix: Option<[[f64; 4]; 4]>,
+    scale: Option<ScaleDesc>,
+    rotate_x: Option<f64>,
+    rotate_y: Option<f64>,
+    rotate_z: Option<f64>,
+    translate: Option<[f64; 3]>,
+    #[serde(default)]
+    keyframes: Vec<KeyframeDesc>,
+    #[serde(flatten)]
+    shape: ShapeDesc,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    PerAxis([f64; 3]),
+}
+
+impl ScaleDesc {
+    fn factors(self) -> Vec3 {
+        match self {
+            ScaleDesc::Uniform(s) => Vec3::new(s, s, s),
+            ScaleDesc::PerAxis(s) => vec3(s),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    scale: Option<ScaleDesc>,
+    axis: Option<[f64; 3]>,
+    #[serde(default)]
+    angle: f64,
+    translate: Option<[f64; 3]>,
+}
+
+///
+/// How an object or group is placed, see the module docs
+///
+struct Placement {
+    matrix: Option<[[f64; 4]; 4]>,
+    scale: Option<ScaleDesc>,
+    rotate_x: Option<f64>,
+    rotate_y: Option<f64>,
+    rotate_z: Option<f64>,
+    translate: Option<[f64; 3]>,
+    keyframes: Vec<KeyframeDesc>,
+}
+
+impl GroupDesc {
+    fn placement(&self) -> Placement {
+        Placement {
+            matrix: self.matrix,
+            scale: self.scale,
+            rotate_x: self.rotate_x,
+            rotate_y: self.rotate_y,
+            rotate_z: self.rotate_z,
+            translate: self.translate,
+            keyframes: self.keyframes.clone(),
+        }
+    }
+}
+
+impl ObjectDesc {
+    fn placement(&self) -> Placement {
+        Placement {
+            matrix: self.matrix,
+            scale: self.scale,
+            rotate_x: self.rotate_x,
+            rotate_y: self.rotate_y,
+            rotate_z: self.rotate_z,
+            translate: self.translate,
+            keyframes: self.keyframes.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+        // End position of a sphere moving during the exposure
+        center1: Option<[f64; 3]>,
+    },
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Box {
+        a: [f64; 3],
+        b: [f64; 3],
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+    },
+    Obj {
+        path: PathBuf,
+        // Material for faces without a usemtl statement
+        material: Option<String>,
+    },
+    ConstantMedium {
+        boundary: BoundaryDesc,
+        density: f64,
+        albedo: TextureRef,
+    },
+}
+
+///
+/// The shape of a constant medium. Its surface is invisible, so it has no material.
+///
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BoundaryDesc {
+    Sphere { center: [f64; 3], radius: f64 },
+    Box { a: [f64; 3], b: [f64; 3] },
+}
+
+struct Builder<'a> {
+    path: &'a Path,
+    dir: PathBuf,
+    source: &'a str,
+    desc: &'a SceneDesc,
+    textures: HashMap<String, Texture>,
+    materials: HashMap<String, Material>,
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn color(c: [f64; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
+
+impl<'a> Builder<'a> {
+    ///
+    /// "file:line" of the start of a span, for error messages
+    ///
+    fn location(&self, span: std::ops::Range<usize>) -> String {
+        let line = self.source[..span.start].matches('\n').count() + 1;
+        format!("{}:{}", self.path.display(), line)
+    }
+
+    fn camera(&mut self) -> Result<Camera> {
+        let c = &self.desc.camera;
+        let basic = Camera::new_basic();
+        let mut camera = Camera {
+            aspect_ratio: c.aspect_ratio.unwrap_or(basic.aspect_ratio),
+            image_width: c.image_width.unwrap_or(basic.image_width),
+            samples_per_pixel: c.samples_per_pixel.unwrap_or(basic.samples_per_pixel),
+            max_depth: c.max_depth.unwrap_or(basic.max_depth),
+            vfov: c.vfov.unwrap_or(basic.vfov),
+            look_from: c.look_from.map_or(basic.look_from, vec3),
+            look_at: c.look_at.map_or(basic.look_at, vec3),
+            v_up: c.v_up.map_or(basic.v_up, vec3),
+            defocus_angle: c.defocus_angle.unwrap_or(basic.defocus_angle),
+            focus_dist: c.focus_dist.unwrap_or(basic.focus_dist),
+            background: c.background.map(color),
+            sampling: c.sampling.unwrap_or(basic.sampling),
+            heuristic: c.heuristic.unwrap_or(basic.heuristic),
+            shutter_open: c.shutter_open.unwrap_or(basic.shutter_open),
+            shutter_close: c.shutter_close.unwrap_or(basic.shutter_close),
+            shutter_curve: match &c.shutter_curve {
+                Some(ShutterCurveDesc::Named(name)) => name.parse(),
+                Some(ShutterCurveDesc::Weights(weights)) => ShutterCurve::custom(weights.clone()),
+                None => Ok(basic.shutter_curve),
+            }
+            .with_context(|| format!("{}: in [camera]", self.path.display()))?,
+            motion: Vec::new(),
+            projection: c.projection.unwrap_or(basic.projection),
+            view_width: c.view_width.unwrap_or(basic.view_width),
+            fisheye_fov: c.fisheye_fov.unwrap_or(basic.fisheye_fov),
+            eye: basic.eye,
+            ipd: c.ipd.unwrap_or(basic.ipd),
+            convergence_dist: c.convergence_dist.or(basic.convergence_dist),
+            stereo_mode: c.stereo_mode.unwrap_or(basic.stereo_mode),
+            aperture: match (c.aperture_blades, &c.aperture_texture) {
+                (None, _) if c.aperture_rotation.is_some() => bail!(
+                    "{}: in [camera]: aperture_rotation needs aperture_blades",
+                    self.path.display()
+                ),
+                (Some(_), Some(_)) => bail!(
+                    "{}: in [camera]: aperture_blades and aperture_texture cannot both be set",
+                    self.path.display()
+                ),
+                (Some(blades), None) => Aperture::Polygon {
+                    blades,
+                    rotation: c.aperture_rotation.unwrap_or(0.0),
+                },
+                (None, Some(name)) => Aperture::Texture(
+                    self.texture(name, &mut vec![])
+                        .with_context(|| format!("{}: in [camera]", self.path.display()))?,
+                ),
+                (None, None) => basic.aperture,
+            },
+            anamorphic_squeeze: c.anamorphic_squeeze.unwrap_or(basic.anamorphic_squeeze),
+            vignetting: c.vignetting.unwrap_or(basic.vignetting),
+            filter: c.filter.unwrap_or(basic.filter),
+            filter_radius: c.filter_radius.or(basic.filter_radius),
+            spectral: c.spectral.unwrap_or(basic.spectral),
+        };
+        camera
+            .aperture
+            .validate()
+            .with_context(|| format!("{}: in [camera]", self.path.display()))?;
+        if camera.anamorphic_squeeze <= 0.0 {
+            bail!(
+                "{}: in [camera]: anamorphic_squeeze must be greater than 0",
+                self.path.display()
+            );
+        }
+        if camera.filter_radius.is_some_and(|r| r < 0.5) {
+            bail!(
+                "{}: in [camera]: filter_radius must be at least 0.5",
+                self.path.display()
+            );
+        }
+        if camera.vignetting < 0.0 {
+            bail!(
+                "{}: in [camera]: vignetting cannot be negative",
+                self.path.display()
+            );
+        }
+        if camera.ipd < 0.0 {
+            bail!(
+                "{}: in [camera]: ipd cannot be negative",
+                self.path.display()
+            );
+        }
+        if camera.convergence_dist.is_some_and(|d| d <= 0.0) {
+            bail!(
+                "{}: in [camera]: convergence_dist must be greater than 0",
+                self.path.display()
+            );
+        }
+        if camera.view_width <= 0.0 {
+            bail!(
+                "{}: in [camera]: view_width must be greater than 0",
+                self.path.display()
+            );
+        }
+        if !(camera.fisheye_fov > 0.0 && camera.fisheye_fov <= 360.0) {
+            bail!(
+                "{}: in [camera]: fisheye_fov must be between 0 and 360 degrees",
+                self.path.display()
+            );
+        }
+        if camera.shutter_close < camera.shutter_open {
+            bail!(
+                "{}: in [camera]: the shutter closes before it opens",
+                self.path.display()
+            );
+        }
+
+        camera.motion = c
+            .motion
+            .iter()
+            .map(|k| CameraKeyframe {
+                look_from: k.look_from.map_or(camera.look_from, vec3),
+                look_at: k.look_at.map_or(camera.look_at, vec3),
+                v_up: k.v_up.map_or(camera.v_up, vec3),
+                vfov: k.vfov.unwrap_or(camera.vfov),
+                focus_dist: k.focus_dist.unwrap_or(camera.focus_dist),
+                defocus_angle: k.defocus_angle.unwrap_or(camera.defocus_angle),
+                ..camera.keyframe(k.time)
+            })
+            .collect();
+
+        Ok(camera)
+    }
+
+    fn animation(&self) -> Result<Option<Animation>> {
+        let Some(a) = &self.desc.animation else {
+            return Ok(None);
+        };
+        let c = &self.desc.camera;
+        if c.shutter_open.is_some() || c.shutter_close.is_some() {
+            bail!(
+                "{}: in [camera]: an animation's shutter is set by its shutter_angle instead",
+                self.path.display()
+            );
+        }
+        let basic = Animation::new(a.frames);
+        let animation = Animation {
+            start_time: a.start_time.unwrap_or(basic.start_time),
+            end_time: a.end_time.unwrap_or(basic.end_time),
+            shutter_angle: a.shutter_angle.unwrap_or(basic.shutter_angle),
+            ..basic
+        };
+        animation
+            .validate()
+            .with_context(|| format!("{}: in [animation]", self.path.display()))?;
+        Ok(Some(animation))
+    }
+
+    ///
+    /// Resolves a texture by name, building it (and any texture it refers to) on first use.
+    /// `stack` holds the textures currently being built, to catch reference cycles.
+    ///
+    fn texture(&mut self, name: &str, stack: &mut Vec<String>) -> Result<Texture> {
+        if let Some(tex) = self.textures.get(name) {
+            return Ok(tex.clone());
+        }
+        let desc = self
+            .desc
+            .textures
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown texture `{name}`"))?;
+        let loc = self.location(desc.span());
+
+        if stack.iter().any(|n| n == name) {
+            bail!("{loc}: texture `{name}` refers to itself");
+        }
+        stack.push(name.to_string());
+
+        let tex: Texture = match desc.get_ref() {
+            TextureDesc::Solid { color: c } => SolidColor::new(color(*c)).into(),
+            TextureDesc::Checker { scale, even, odd } => {
+                let even = self
+                    .texture_ref(even, stack)
+                    .with_context(|| format!("{loc}: in texture `{name}`"))?;
+                let odd = self
+                    .texture_ref(odd, stack)
+                    .with_context(|| format!("{loc}: in texture `{name}`"))?;
+                Checker::from_textures(*scale, even, odd).into()
+            }
+            TextureDesc::Image { path } => Image::load(self.dir.join(path))
+                .with_context(|| format!("{loc}: in texture `{name}`"))?
+                .into(),
+            TextureDesc::Noise { scale, seed } => Noise::new(*scale).with_seed(*seed).into(),
+            TextureDesc::Turbulence { scale, depth, seed } => {
+                TurbNoise::new(*scale, *depth).with_seed(*seed).into()
+            }
+            TextureDesc::Marble { scale, depth, seed } => {
+                MarbleNoise::new(*scale, *depth).with_seed(*seed).into()
+            }
+        };
+
+        stack.pop();
+        self.textures.insert(name.to_string(), tex.clone());
+        Ok(tex)
+    }
+
+    fn texture_ref(&mut self, tex: &TextureRef, stack: &mut Vec<String>) -> Result<Texture> {
+        match tex {
+            TextureRef::Color(c) => Ok(SolidColor::new(color(*c)).into()),
+            TextureRef::Named(name) => self.texture(name, stack),
+        }
+    }
+
+    fn film(&mut self, film: &FilmDesc) -> Result<ThinFilm> {
+        if film.refraction_index <= 0.0 {
+            bail!("film refraction_index must be greater than 0");
+        }
+        if film.thickness < 0.0 {
+            bail!("film thickness cannot be negative");
+        }
+        Ok(match &film.thickness_texture {
+            Some(tex) => ThinFilm::textured(
+                film.refraction_index,
+                self.texture_ref(tex, &mut vec![])?,
+                film.thickness,
+            ),
+            None => ThinFilm::new(film.refraction_index, film.thickness),
+        })
+    }
+
+    fn material(&mut self, name: &str) -> Result<Material> {
+        if let Some(mat) = self.materials.get(name) {
+            return Ok(mat.clone());
+        }
+        let desc = self
+            .desc
+            .materials
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown material `{name}`"))?;
+        let loc = self.location(desc.span());
+        let context = || format!("{loc}: in material `{name}`");
+
+        let mat: Material = match desc.get_ref() {
+            MaterialDesc::Lambertian { albedo } => Lambertian::from_texture(
+                self.texture_ref(albedo, &mut vec![])
+                    .with_context(context)?,
+            )
+            .into(),
+            MaterialDesc::Metal { albedo, fuzz } => Metal::new(color(*albedo), *fuzz).into(),
+            MaterialDesc::Conductor {
+                preset,
+                eta,
+                k,
+                roughness,
+                roughness_u,
+                roughness_v,
+                film,
+            } => {
+                let (eta, k) = match (preset, eta, k) {
+                    (Some(preset), None, None) => preset.ior(),
+                    (None, Some(eta), Some(k)) => (color(*eta), color(*k)),
+                    _ => bail!("{}: needs either a preset, or both eta and k", context()),
+                };
+                let (u, v) = (
+                    roughness_u.unwrap_or(*roughness),
+                    roughness_v.unwrap_or(*roughness),
+                );
+                if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+                    bail!("{}: roughness must be between 0 and 1", context());
+                }
+                let metal = Conductor::anisotropic(eta, k, u, v);
+                match film {
+                    Some(film) => metal.with_film(self.film(film).with_context(context)?),
+                    None => metal,
+                }
+                .into()
+            }
+            MaterialDesc::Dielectric {
+                refraction_index,
+                cauchy,
+                sellmeier,
+                roughness,
+                absorption,
+                transmittance,
+                transmittance_distance,
+                film,
+            } => {
+                if !(0.0..=1.0).contains(roughness) {
+                    bail!("{}: roughness must be between 0 and 1", context());
+                }
+                let glass = match (refraction_index, cauchy, sellmeier) {
+                    (Some(ri), None, None) => Dielectric::rough(*ri, *roughness),
+                    (None, Some([a, b]), None) => Dielectric::rough(1.0, *roughness)
+                        .with_dispersion(Dispersion::Cauchy { a: *a, b: *b }),
+                    (None, None, Some(SellmeierDesc { b, c })) => {
+                        Dielectric::rough(1.0, *roughness)
+                            .with_dispersion(Dispersion::Sellmeier { b: *b, c: *c })
+                    }
+                    _ => bail!(
+                        "{}: needs exactly one of refraction_index, cauchy and sellmeier",
+                        context()
+                    ),
+                };
+                let glass = match film {
+                    Some(film) => glass.with_film(self.film(film).with_context(context)?),
+                    None => glass,
+                };
+                match (absorption, transmittance) {
+                    (Some(_), Some(_)) => {
+                        bail!(
+                            "{}: absorption and transmittance cannot both be set",
+                            context()
+                        )
+                    }
+                    (Some(a), None) => {
+                        if a.iter().any(|c| *c < 0.0) {
+                            bail!("{}: absorption cannot be negative", context());
+                        }
+                        glass.with_absorption(color(*a))
+                    }
+                    (None, Some(t)) => {
+                        if t.iter().any(|c| !(*c > 0.0 && *c <= 1.0)) {
+                            bail!("{}: transmittance must be above 0 and at most 1", context());
+                        }
+                        let distance = transmittance_distance.unwrap_or(1.0);
+                        if distance <= 0.0 {
+                            bail!(
+                                "{}: transmittance_distance must be greater than 0",
+                                context()
+                            );
+                        }
+                        glass.with_transmittance(color(*t), distance)
+                    }
+                    (None, None) => glass,
+                }
+                .into()
+            }
+            MaterialDesc::DiffuseLight { emit } => DiffuseLight::from_texture(
+                self.texture_ref(emit, &mut vec![]).with_context(context)?,
+            )
+            .into(),
+            MaterialDesc::Isotropic { albedo } => Isotropic::from_texture(
+                self.texture_ref(albedo, &mut vec![])
+                    .with_context(context)?,
+            )
+            .into(),
+        };
+
+        self.materials.insert(name.to_string(), mat.clone());
+        Ok(mat)
+    }
+
+    ///
+    /// Builds a shape and places it. A medium's placement moves its boundary instead, as the
+    /// built-in scenes do, so that its density stays per unit of the world's length.
+    ///
+    fn placed_shape(&mut self, shape: &ShapeDesc, placement: Placement) -> Result<Hittable> {
+        let object: Hittable = match shape {
+            ShapeDesc::Sphere {
+                center,
+                radius,
+                material,
+                center1,
+            } => {
+                let mat = self.material(material)?;
+                match center1 {
+                    Some(center1) => {
+                        Sphere::new_moving(vec3(*center), vec3(*center1), *radius, mat).into()
+                    }
+                    None => Sphere::new(vec3(*center), *radius, mat).into(),
+                }
+            }
+            ShapeDesc::Quad { q, u, v, material } => {
+                Quad::new(vec3(*q), vec3(*u), vec3(*v), self.material(material)?).into()
+            }
+            ShapeDesc::Box { a, b, material } => {
+                Quad::new_box(vec3(*a), vec3(*b), self.material(material)?).into()
+            }
+            ShapeDesc::Triangle { vertices, material } => Triangle::new(
+                vec3(vertices[0]),
+                vec3(vertices[1]),
+                vec3(vertices[2]),
+                self.material(material)?,
+            )
+            .into(),
+            ShapeDesc::Obj { path, material } => match material {
+                Some(material) => {
+                    obj::load_with_default(self.dir.join(path), self.material(material)?)?
+                }
+                None => obj::load(self.dir.join(path))?,
+            },
+            ShapeDesc::ConstantMedium {
+                boundary,
+                density,
+                albedo,
+            } => {
+                // The boundary's material is never used, any will do
+                let invisible = Lambertian::new(Color::black());
+                let boundary: Hittable = match boundary {
+                    BoundaryDesc::Sphere { center, radius } => {
+                        Sphere::new(vec3(*center), *radius, invisible).into()
+                    }
+                    BoundaryDesc::Box { a, b } => {
+                        Quad::new_box(vec3(*a), vec3(*b), invisible).into()
+                    }
+                };
+                let boundary = place(boundary, placement)?;
+                let tex = self.texture_ref(albedo, &mut vec![])?;
+                return Ok(ConstantMedium::with_texture(boundary, *density, tex).into());
+            }
+        };
+        place(object, placement)
+    }
+
+    ///
+    /// Builds the world, and the list of its objects marked as lights
+    ///
+    fn world(&mut self) -> Result<(Hittable, HittableList)> {
+        let desc = self.desc;
+
+        // Children of the world (None) and of each group, in file order
+        let mut children: HashMap<Option<&str>, Vec<Hittable>> = HashMap::new();
+        let mut lights = HittableList::new();
+
+        for object in desc.objects.iter() {
+            let loc = self.location(object.span());
+            let o = object.get_ref();
+
+            let parent = o.group.as_deref();
+            if let Some(group) = parent {
+                if !desc.groups.contains_key(group) {
+                    bail!("{loc}: unknown group `{group}`");
+                }
+            }
+
+            let hittable = self
+                .placed_shape(&o.shape, o.placement())
+                .with_context(|| format!("{loc}: in object"))?;
+
+            if o.light {
+                if parent.is_some() {
+                    bail!("{loc}: lights cannot be inside a group");
+                }
+                if matches!(o.shape, ShapeDesc::ConstantMedium { .. }) {
+                    bail!("{loc}: a constant medium cannot be a light");
+                }
+                lights.add(hittable.clone());
+            }
+
+            children.entry(parent).or_default().push(hittable);
+        }
+
+        // Build groups children-first, so every group is complete before it is added to its parent
+        let mut built: HashSet<&str> = HashSet::new();
+        let mut names: Vec<&String> = desc.groups.keys().collect();
+        names.sort_by_key(|name| desc.groups[*name].span().start);
+        for name in names.iter() {
+            self.build_group(name, &mut children, &mut built, &mut Vec::new())?;
+        }
+
+        let world: HittableList = children
+            .remove(&None)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        Ok((world.into(), lights))
+    }
+
+    fn build_group(
+        &self,
+        name: &'a str,
+        children: &mut HashMap<Option<&'a str>, Vec<Hittable>>,
+        built: &mut HashSet<&'a str>,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<()> {
+        if built.contains(name) {
+            return Ok(());
+        }
+
+        let desc = &self.desc.groups[name];
+        let loc = self.location(desc.span());
+        let g = desc.get_ref();
+
+        if stack.contains(&name) {
+            bail!("{loc}: group `{name}` is nested inside itself");
+        }
+
+        // Every group nested inside this one has to be built first
+        stack.push(name);
+        let mut nested: Vec<(&'a String, usize)> = self
+            .desc
+            .groups
+            .iter()
+            .filter(|(_, d)| d.get_ref().group.as_deref() == Some(name))
+            .map(|(n, d)| (n, d.span().start))
+            .collect();
+        nested.sort_by_key(|(_, start)| *start);
+        for (n, _) in nested {
+            self.build_group(n, children, built, stack)?;
+        }
+        stack.pop();
+
+        let objects = children.remove(&Some(name)).unwrap_or_default();
+        if objects.is_empty() {
+            bail!("{loc}: group `{name}` is empty");
+        }
+        let group: Hittable = match g.kind {
+            GroupKind::List => objects.into_iter().collect::<HittableList>().into(),
+            GroupKind::Bvh => match g.split {
+                Some(split) => BvhNode::with_split(objects, split).into(),
+                None => BvhNode::new(objects).into(),
+            },
+        };
+        let group =
+            place(group, g.placement()).with_context(|| format!("{loc}: in group `{name}`"))?;
+
+        let parent = match g.group.as_deref() {
+            Some(parent) => {
+                let (parent, _) = self
+                    .desc
+                    .groups
+                    .get_key_value(parent)
+                    .ok_or_else(|| anyhow!("{loc}: unknown group `{parent}`"))?;
+                Some(parent.as_str())
+            }
+            None => None,
+        };
+        children.entry(parent).or_default().push(group);
+        built.insert(name);
+
+        Ok(())
+    }
+}
+
+///
+/// Wraps an object in the instances that place it, then in a `KeyframedTransform` if it moves
+///
+fn place(object: Hittable, p: Placement) -> Result<Hittable> {
+    let object = place_static(object, &p)?;
+    if p.keyframes.is_empty() {
+        return Ok(object);
+    }
+
+    let mut keyframes = Vec::with_capacity(p.keyframes.len());
+    for k in &p.keyframes {
+        let mut keyframe = Keyframe::new(k.time);
+        if let Some(scale) = k.scale {
+            keyframe = keyframe.with_scale(scale.factors());
+        }
+        if k.angle != 0.0 {
+            let axis = k.axis.map_or(Vec3::new(0.0, 1.0, 0.0), vec3);
+            if axis.near_zero() {
+                bail!("the keyframe at time {} rotates around a zero axis", k.time);
+            }
+            keyframe = keyframe.with_rotation(axis, k.angle);
+        }
+        if let Some(offset) = k.translate {
+            keyframe = keyframe.with_translation(vec3(offset));
+        }
+        keyframes.push(keyframe);
+    }
+    Ok(KeyframedTransform::new(object, keyframes)?.into())
+}
+
+///
+/// Applies the fixed part of a placement. Rotations about Y and translations on their own use
+/// the cheaper `YRotate` and `Translate`, anything else a single `Transform`.
+///
+fn place_static(object: Hittable, p: &Placement) -> Result<Hittable> {
+    if p.matrix.is_none() && p.scale.is_none() && p.rotate_x.is_none() && p.rotate_z.is_none() {
+        let object = match p.rotate_y {
+            Some(angle) => YRotate::new(object, angle).into(),
+            None => object,
+        };
+        return Ok(match p.translate {
+            Some(offset) => Translate::new(object, vec3(offset)).into(),
+            None => object,
+        });
+    }
+
+    let mut transform = p.matrix.map_or(Matrix4::identity(), Matrix4::new);
+    if let Some(scale) = p.scale {
+        transform = transform.then(Matrix4::scaling(scale.factors()));
+    }
+    if let Some(angle) = p.rotate_x {
+        transform = transform.then(Matrix4::rotation_x(angle));
+    }
+    if let Some(angle) = p.rotate_y {
+        transform = transform.then(Matrix4::rotation_y(angle));
+    }
+    if let Some(angle) = p.rotate_z {
+        transform = transform.then(Matrix4::rotation_z(angle));
+    }
+    if let Some(offset) = p.translate {
+        transform = transform.then(Matrix4::translation(vec3(offset)));
+    }
+
+    // `Transform::new` reports what is wrong with a matrix from the file
+    if p.matrix.is_none() && transform.inverse().is_none() {
+        bail!("the placement flattens the object, it cannot be inverted");
+    }
+    Ok(Transform::new(object, transform)?.into())
+}
@@ -0,0 +1,221 @@
+//!
+//! Spectral rendering. Each camera sample follows a few hero wavelengths at once, carrying the
+//! radiance at each in one channel of a `Color`, and converts the result to RGB through the CIE
+//! color matching functions. RGB colors of textures, lights and backgrounds are uplifted to
+//! smooth spectra with Smits' method.
+//!
+
+use once_cell::sync::Lazy;
+
+use crate::{color::Color, rng::random};
+
+// Range of wavelengths rendered, in nanometers
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+// Wavelengths per sample, one for each channel of a `Color`
+const HERO_COUNT: usize = 3;
+
+///
+/// The wavelengths a ray carries, in nanometers. The first is the hero wavelength, the others
+/// are spread evenly from it across the rendered range. Once a dispersive material bends each
+/// wavelength its own way only the hero is followed.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wavelengths {
+    lambda: [f64; HERO_COUNT],
+    hero_only: bool,
+}
+
+impl Wavelengths {
+    pub fn sample() -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = random::<f64>() * range;
+        Self {
+            lambda: std::array::from_fn(|i| {
+                LAMBDA_MIN + (hero + i as f64 * range / HERO_COUNT as f64) % range
+            }),
+            hero_only: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn is_hero_only(&self) -> bool {
+        self.hero_only
+    }
+
+    ///
+    /// Drops all but the hero wavelength. Scaling the radiance by the returned weight keeps the
+    /// estimate unbiased.
+    ///
+    pub fn terminate_secondary(self) -> (Self, Color) {
+        let weight = Color::new(HERO_COUNT as f64, 0.0, 0.0);
+        let hero_only = Self {
+            hero_only: true,
+            ..self
+        };
+        (hero_only, weight)
+    }
+
+    ///
+    /// Values at these wavelengths of the smooth spectrum that `rgb`, a linear sRGB color, was
+    /// uplifted to
+    ///
+    pub fn uplift(&self, rgb: Color) -> Color {
+        self.map(|lambda| smits(rgb, lambda))
+    }
+
+    ///
+    /// Values of `spectrum(lambda)` at these wavelengths
+    ///
+    pub fn map(&self, spectrum: impl Fn(f64) -> f64) -> Color {
+        let [a, b, c] = self.lambda.map(spectrum);
+        Color::new(a, b, c)
+    }
+
+    ///
+    /// Linear sRGB color of `radiance` carried at these wavelengths, for one sample. Averaged
+    /// over many samples, it converges to the color of the full spectrum.
+    ///
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        let values = [radiance.r(), radiance.g(), radiance.b()];
+        // Wavelengths are drawn uniformly, so each is weighed by the range over their count
+        let scale = (LAMBDA_MAX - LAMBDA_MIN) / HERO_COUNT as f64;
+        let xyz = self
+            .lambda
+            .iter()
+            .zip(values)
+            .map(|(lambda, value)| cie_xyz(*lambda) * (value * scale))
+            .sum::<Color>();
+        let rgb = xyz_to_rgb(xyz);
+        let white = *WHITE_RGB;
+        Color::new(
+            rgb.r() / white.r(),
+            rgb.g() / white.g(),
+            rgb.b() / white.b(),
+        )
+    }
+}
+
+// Linear sRGB of a spectrum of 1 at every wavelength, which is scaled back to white so that
+// uplifted colors keep their RGB values
+static WHITE_RGB: Lazy<Color> = Lazy::new(|| unscaled_rgb(|_| 1.0));
+
+///
+/// Linear sRGB color of a surface reflecting `reflectance(lambda)` of the light at each
+/// wavelength, under a light of equal energy at all of them. A reflectance of 1 everywhere is
+/// white.
+///
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    let rgb = unscaled_rgb(reflectance);
+    let white = *WHITE_RGB;
+    Color::new(
+        rgb.r() / white.r(),
+        rgb.g() / white.g(),
+        rgb.b() / white.b(),
+    )
+}
+
+fn unscaled_rgb(spectrum: impl Fn(f64) -> f64) -> Color {
+    // Fine enough to follow the color matching functions, yet cheap enough to run per hit
+    const STEPS: usize = 64;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / STEPS as f64;
+    let xyz = (0..STEPS)
+        .map(|i| {
+            let lambda = LAMBDA_MIN + (i as f64 + 0.5) * step;
+            cie_xyz(lambda) * (spectrum(lambda) * step)
+        })
+        .sum::<Color>();
+    xyz_to_rgb(xyz)
+}
+
+///
+/// The CIE 1931 color matching functions, in the multi-lobe fit of Wyman, Sloan and Shirley
+/// (2013), as X, Y and Z
+///
+fn cie_xyz(lambda: f64) -> Color {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    Color::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_rgb(xyz: Color) -> Color {
+    let (x, y, z) = (xyz.r(), xyz.g(), xyz.b());
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+// Smits' basis spectra, in 10 bins evenly covering LAMBDA_MIN to LAMBDA_MAX
+const WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+///
+/// Value at `lambda` of the spectrum Smits (1999) builds for `rgb`: as much white as the
+/// smallest component, then the secondary and primary colors making up the rest
+///
+fn smits(rgb: Color, lambda: f64) -> f64 {
+    // Interpolated linearly between the centers of the bins
+    let x = ((lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * 10.0 - 0.5).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as f64;
+    let at = |spectrum: &[f64; 10]| (1.0 - t) * spectrum[i] + t * spectrum[i + 1];
+
+    let (r, g, b) = (rgb.r(), rgb.g(), rgb.b());
+    if r <= g && r <= b {
+        r * at(&WHITE)
+            + if g <= b {
+                (g - r) * at(&CYAN) + (b - g) * at(&BLUE)
+            } else {
+                (b - r) * at(&CYAN) + (g - b) * at(&GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * at(&WHITE)
+            + if r <= b {
+                (r - g) * at(&MAGENTA) + (b - r) * at(&BLUE)
+            } else {
+                (b - g) * at(&MAGENTA) + (r - b) * at(&RED)
+            }
+    } else {
+        b * at(&WHITE)
+            + if r <= g {
+                (r - b) * at(&YELLOW) + (g - r) * at(&GREEN)
+            } else {
+                (g - b) * at(&YELLOW) + (r - g) * at(&RED)
+            }
+    }
+}
@@ -1,5 +1,6 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
+use anyhow::{Context, Result};
 use image::io::Reader as ImageReader;
 use image::RgbImage;
 
@@ -10,13 +11,21 @@ pub struct Image {
 
 impl Image {
     pub fn new(filename: &str) -> Self {
+        Self::load(filename).expect("Cannot load image texture")
+    }
+
+    ///
+    /// Fallible version of `new`, for images whose path comes from user input
+    ///
+    pub fn load(filename: impl AsRef<Path>) -> Result<Self> {
+        let filename = filename.as_ref();
         let image = ImageReader::open(filename)
-            .expect("Cannot read image file")
+            .with_context(|| format!("Cannot read image file {}", filename.display()))?
             .decode()
-            .expect("Failed to decode image")
+            .with_context(|| format!("Failed to decode image {}", filename.display()))?
             .to_rgb8();
-        Self {
+        Ok(Self {
             image: Arc::new(image),
-        }
+        })
     }
 }
@@ -75,7 +75,7 @@ impl Texture {
                 let (width, height) = img.image.dimensions();
 
                 // if image is empty, return solid cyan for debugging
-                if width <= 0 && height <= 0 {
+                if width == 0 && height == 0 {
                     return Color::new(0.0, 1.0, 1.0);
                 }
 
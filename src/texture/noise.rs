@@ -9,10 +9,19 @@ pub struct Noise {
 impl Noise {
     pub fn new(scale: f64) -> Self {
         Self {
-            noise: PerlinNoise::new(),
+            noise: PerlinNoise::new(0),
             scale,
         }
     }
+
+    ///
+    /// Draws the noise for `seed` instead of 0. Textures with the same seed share their noise,
+    /// whatever the seed of the render.
+    ///
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = PerlinNoise::new(seed);
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -25,11 +34,20 @@ pub struct TurbNoise {
 impl TurbNoise {
     pub fn new(scale: f64, depth: u32) -> Self {
         Self {
-            noise: PerlinNoise::new(),
+            noise: PerlinNoise::new(0),
             scale,
             depth,
         }
     }
+
+    ///
+    /// Draws the noise for `seed` instead of 0. Textures with the same seed share their noise,
+    /// whatever the seed of the render.
+    ///
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = PerlinNoise::new(seed);
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -42,9 +60,18 @@ pub struct MarbleNoise {
 impl MarbleNoise {
     pub fn new(scale: f64, depth: u32) -> Self {
         Self {
-            noise: PerlinNoise::new(),
+            noise: PerlinNoise::new(0),
             scale,
             depth,
         }
     }
+
+    ///
+    /// Draws the noise for `seed` instead of 0. Textures with the same seed share their noise,
+    /// whatever the seed of the render.
+    ///
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = PerlinNoise::new(seed);
+        self
+    }
 }
@@ -1,6 +1,12 @@
-use rand::{distributions::Uniform, Rng};
+use rand::{
+    distributions::{Distribution, Uniform},
+    Rng,
+};
 
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    rng,
+    vec3::{Point3, Vec3},
+};
 
 #[derive(Clone)]
 pub struct PerlinNoise {
@@ -13,17 +19,25 @@ pub struct PerlinNoise {
 const POINT_COUNT: usize = 256;
 
 impl PerlinNoise {
-    pub fn new() -> Self {
-        let mut rng = rand::thread_rng();
+    ///
+    /// Noise drawn from the generator for `seed`, the same every time
+    ///
+    pub fn new(seed: u64) -> Self {
+        let mut rng = rng::noise_generator(seed);
         let mut rand_vec: Vec<Vec3> = Vec::with_capacity(POINT_COUNT);
         let rand_vec_dist = Uniform::from(-1.0..=1.0);
         for _ in 0..POINT_COUNT {
-            rand_vec.push(Vec3::random_dist(&rand_vec_dist, &mut rng).unit_vector());
+            let v = Vec3::new(
+                rand_vec_dist.sample(&mut rng),
+                rand_vec_dist.sample(&mut rng),
+                rand_vec_dist.sample(&mut rng),
+            );
+            rand_vec.push(v.unit_vector());
         }
 
-        let perm_x = perlin_generate_perm();
-        let perm_y = perlin_generate_perm();
-        let perm_z = perlin_generate_perm();
+        let perm_x = perlin_generate_perm(&mut rng);
+        let perm_y = perlin_generate_perm(&mut rng);
+        let perm_z = perlin_generate_perm(&mut rng);
 
         Self {
             rand_vec,
@@ -72,17 +86,17 @@ impl PerlinNoise {
     }
 }
 
-fn perlin_generate_perm() -> Vec<usize> {
-    let mut p: Vec<usize> = (0..POINT_COUNT).into_iter().collect();
+fn perlin_generate_perm(rng: &mut impl Rng) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
 
-    permute(&mut p, POINT_COUNT);
+    permute(&mut p, POINT_COUNT, rng);
 
     p
 }
 
-fn permute(p: &mut [usize], n: usize) {
+fn permute(p: &mut [usize], n: usize, rng: &mut impl Rng) {
     for i in (1..(n - 1)).rev() {
-        let target = rand::thread_rng().gen_range(0..=i);
+        let target = rng.gen_range(0..=i);
         let tmp = p[i];
         p[i] = p[target];
         p[target] = tmp
@@ -96,9 +110,9 @@ fn perlin_interpolation(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
 
     let mut acc = 0.0;
 
-    for i in 0..2 {
-        for j in 0..2 {
-            for k in 0..2 {
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, corner) in row.iter().enumerate() {
                 let fi = i as f64;
                 let fj = j as f64;
                 let fk = k as f64;
@@ -106,7 +120,7 @@ fn perlin_interpolation(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
                 acc += (fi * uu + (1.0 - fi) * (1.0 - uu))
                     * (fj * vv + (1.0 - fj) * (1.0 - vv))
                     * (fk * ww + (1.0 - fk) * (1.0 - ww))
-                    * c[i][j][k].dot(weight_v);
+                    * corner.dot(weight_v);
             }
         }
     }
@@ -0,0 +1,309 @@
+//!
+//! Turning linear radiance into colors for 8-bit images: the exposure scales the image, a tone
+//! mapping operator compresses the range of brightness into 0..=1, and a transfer function
+//! encodes the result for display.
+//!
+
+use std::str::FromStr;
+
+use serde::{de::IntoDeserializer, Deserialize};
+
+use crate::{color::Color, framebuffer::Framebuffer};
+
+///
+/// How brightness beyond white is brought into range
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMap {
+    /// Leave colors as they are, so everything past white clips
+    #[default]
+    Clamp,
+
+    /// L / (1 + L) on luminance, which never quite reaches white
+    Reinhard,
+
+    /// Reinhard, scaled so that luminance at the white point maps to white
+    ExtendedReinhard,
+
+    /// John Hable's filmic curve from Uncharted 2
+    Hable,
+
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms
+    Aces,
+}
+
+///
+/// How tone mapped linear values are encoded for display
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transfer {
+    /// Square root, an approximation of a gamma of 2.2
+    #[default]
+    Gamma2,
+
+    /// The piecewise sRGB curve
+    Srgb,
+}
+
+impl FromStr for ToneMap {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+impl FromStr for Transfer {
+    type Err = serde::de::value::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s.into_deserializer())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ToneMapping {
+    pub exposure: f64,     // Stops to brighten the image by, or darken when negative
+    pub operator: ToneMap, // Compresses the exposed colors
+    pub white_point: Option<f64>, // Luminance ExtendedReinhard maps to white, the brightest pixel's if None
+    pub transfer: Transfer,       // Encodes the compressed colors for display
+}
+
+// Hable's curve parameters and linear white point
+const HABLE_A: f64 = 0.15; // Shoulder strength
+const HABLE_B: f64 = 0.50; // Linear strength
+const HABLE_C: f64 = 0.10; // Linear angle
+const HABLE_D: f64 = 0.20; // Toe strength
+const HABLE_E: f64 = 0.02; // Toe numerator
+const HABLE_F: f64 = 0.30; // Toe denominator
+const HABLE_WHITE: f64 = 11.2;
+const HABLE_EXPOSURE_BIAS: f64 = 2.0;
+
+// sRGB to the ACES fit's input space, with the RRT's saturation adjustment folded in
+const ACES_INPUT: [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+// Back from the ODT's output space to sRGB
+const ACES_OUTPUT: [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+impl ToneMapping {
+    ///
+    /// Maps every pixel of `image`, returning colors in 0..=1 encoded for display. Values may
+    /// still exceed 1 with the Clamp operator.
+    ///
+    pub fn map_image(&self, image: &Framebuffer) -> Vec<Color> {
+        let scale = 2f64.powf(self.exposure);
+        let white = match (self.operator, self.white_point) {
+            (_, Some(white)) => white,
+            (ToneMap::ExtendedReinhard, None) => image
+                .pixels()
+                .iter()
+                .map(|c| (*c * scale).luminance())
+                .fold(0.0, f64::max),
+            (_, None) => 1.0,
+        };
+        image
+            .pixels()
+            .iter()
+            .map(|c| self.encode(self.tone_map(*c * scale, white)))
+            .collect()
+    }
+
+    ///
+    /// Compresses an exposed linear color, `white` being the white point luminance
+    ///
+    fn tone_map(&self, c: Color, white: f64) -> Color {
+        match self.operator {
+            ToneMap::Clamp => c,
+
+            ToneMap::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+
+            ToneMap::ExtendedReinhard => {
+                let white_sq = if white > 0.0 { white * white } else { 1.0 };
+                scale_luminance(c, |l| l * (1.0 + l / white_sq) / (1.0 + l))
+            }
+
+            ToneMap::Hable => {
+                let white_scale = 1.0 / hable(HABLE_WHITE);
+                map_channels(c, |x| hable(HABLE_EXPOSURE_BIAS * x) * white_scale)
+            }
+
+            ToneMap::Aces => {
+                let c = multiply(&ACES_INPUT, c);
+                let c = map_channels(c, |x| {
+                    let a = x * (x + 0.0245786) - 0.000090537;
+                    let b = x * (0.983729 * x + 0.4329510) + 0.238081;
+                    a / b
+                });
+                multiply(&ACES_OUTPUT, c)
+            }
+        }
+    }
+
+    fn encode(&self, c: Color) -> Color {
+        match self.transfer {
+            Transfer::Gamma2 => map_channels(c, |x| if x > 0.0 { x.sqrt() } else { 0.0 }),
+            Transfer::Srgb => map_channels(c, |x| {
+                if x <= 0.0031308 {
+                    12.92 * x.max(0.0)
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            }),
+        }
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (HABLE_A, HABLE_B, HABLE_C, HABLE_D, HABLE_E, HABLE_F);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+fn map_channels(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(c.r()), f(c.g()), f(c.b()))
+}
+
+///
+/// Scales a color so that its luminance becomes `f` of its old luminance, keeping its hue
+///
+fn scale_luminance(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    let l = c.luminance();
+    if l > 0.0 {
+        c * (f(l) / l)
+    } else {
+        Color::black()
+    }
+}
+
+fn multiply(m: &[[f64; 3]; 3], c: Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * c.r() + r[1] * c.g() + r[2] * c.b();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMap; 5] = [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        ToneMap::ExtendedReinhard,
+        ToneMap::Hable,
+        ToneMap::Aces,
+    ];
+
+    fn with(operator: ToneMap) -> ToneMapping {
+        ToneMapping {
+            operator,
+            ..Default::default()
+        }
+    }
+
+    fn grey(x: f64) -> Color {
+        Color::new(x, x, x)
+    }
+
+    #[test]
+    fn black_stays_black() {
+        for operator in OPERATORS {
+            let c = with(operator).tone_map(Color::black(), 4.0);
+            // The ACES fit is a hair below 0 at 0
+            assert!(c.luminance().abs() < 1e-3, "{operator:?}: {c:?}");
+        }
+        for transfer in [Transfer::Gamma2, Transfer::Srgb] {
+            let mapping = ToneMapping {
+                transfer,
+                ..Default::default()
+            };
+            assert_eq!(mapping.encode(Color::black()), Color::black());
+        }
+    }
+
+    #[test]
+    fn brighter_stays_brighter() {
+        for operator in OPERATORS {
+            let mapping = with(operator);
+            let mut last = f64::NEG_INFINITY;
+            for i in 0..=200 {
+                let l = mapping.tone_map(grey(i as f64 * 0.1), 4.0).luminance();
+                assert!(l >= last, "{operator:?} at {}", i as f64 * 0.1);
+                last = l;
+            }
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-4;
+
+        // L / (1 + L), which only approaches white
+        let reinhard = with(ToneMap::Reinhard);
+        assert!(near(reinhard.tone_map(grey(1.0), 1.0).luminance(), 0.5));
+        assert!(reinhard.tone_map(grey(1000.0), 1.0).luminance() < 1.0);
+
+        // The white point maps to white, whichever it is
+        let extended = with(ToneMap::ExtendedReinhard);
+        for white in [1.0, 4.0, 16.0] {
+            let l = extended.tone_map(grey(white), white).luminance();
+            assert!(near(l, 1.0), "white point {white}: {l}");
+        }
+
+        // Hable's linear white point, before the exposure bias, maps to white
+        let hable = with(ToneMap::Hable);
+        let l = hable
+            .tone_map(grey(HABLE_WHITE / HABLE_EXPOSURE_BIAS), 1.0)
+            .luminance();
+        assert!(near(l, 1.0), "{l}");
+
+        // Grey stays grey, 1 comes out at 0.619, and bright values level off just past white
+        let aces = with(ToneMap::Aces);
+        let c = aces.tone_map(grey(1.0), 1.0);
+        assert!(
+            near(c.r(), 0.6191) && near(c.g(), 0.6191) && near(c.b(), 0.6191),
+            "{c:?}"
+        );
+        let l = aces.tone_map(grey(100.0), 1.0).luminance();
+        assert!((1.0..=1.02).contains(&l), "{l}");
+    }
+
+    #[test]
+    fn srgb_transfer_is_continuous_at_the_knee() {
+        let srgb = ToneMapping {
+            transfer: Transfer::Srgb,
+            ..Default::default()
+        };
+        let encode = |x: f64| srgb.encode(grey(x)).r();
+
+        const KNEE: f64 = 0.0031308;
+        assert!((encode(KNEE) - 12.92 * KNEE).abs() < 1e-12);
+        assert!((encode(KNEE + 1e-9) - encode(KNEE)).abs() < 1e-6);
+        assert!((encode(1.0) - 1.0).abs() < 1e-12);
+        assert!((encode(0.18) - 0.4613).abs() < 1e-4);
+
+        let gamma = ToneMapping::default();
+        assert_eq!(gamma.encode(grey(0.25)).r(), 0.5);
+    }
+
+    #[test]
+    fn extended_reinhard_whitens_the_brightest_pixel() {
+        let mut image = Framebuffer::new(2, 1);
+        image.set_pixel(0, 0, grey(0.5));
+        image.set_pixel(1, 0, grey(3.0));
+        let mapping = ToneMapping {
+            exposure: 1.0,
+            ..with(ToneMap::ExtendedReinhard)
+        };
+        let pixels = mapping.map_image(&image);
+        assert!((pixels[1].luminance() - 1.0).abs() < 1e-9);
+        assert!(pixels[0].luminance() < pixels[1].luminance());
+    }
+}
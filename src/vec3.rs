@@ -4,11 +4,9 @@ use std::{
 };
 
 use once_cell::sync::Lazy;
-use rand::{
-    distributions::{Distribution, Uniform},
-    random,
-    rngs::ThreadRng,
-};
+use rand::distributions::{Distribution, Uniform};
+
+use crate::rng::{self, random, ThreadRng};
 
 pub type Point3 = Vec3;
 
@@ -35,7 +33,7 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_sphere() -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng::thread_rng();
         loop {
             let p = Vec3::random_dist(&UNIT_SPHERE_DIST, &mut rng);
             if p.length_squared() < 1.0 {
@@ -59,7 +57,7 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_disk() -> Self {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng::thread_rng();
         loop {
             let p = Vec3::new(
                 UNIT_SPHERE_DIST.sample(&mut rng),
@@ -0,0 +1,22 @@
+//!
+//! The BVH split changes how fast a ray finds its hit, never which hit it finds, so both splits
+//! render the same image. Media draw a random distance on every visit, which checks that those
+//! draws don't depend on the order the tree is searched in.
+//!
+
+use raytracer_v2::{hittable::bvh::BvhSplit, scene, Camera};
+
+#[test]
+fn splits_render_the_same_image_with_media() {
+    let builtin = scene::find_scene("book2_final").unwrap();
+    let camera = Camera {
+        image_width: 32,
+        samples_per_pixel: 2,
+        ..(builtin.camera.camera)()
+    };
+    let lights = (builtin.lights)();
+
+    let median = camera.render(&(builtin.world)().with_bvh_split(BvhSplit::Median), &lights);
+    let sah = camera.render(&(builtin.world)().with_bvh_split(BvhSplit::Sah), &lights);
+    assert!(median.pixels() == sah.pixels());
+}
@@ -0,0 +1,85 @@
+//!
+//! The scene files in `scenes/` that share a name with a built-in scene describe the same world
+//! and camera, so both render the same image.
+//!
+
+use std::{fs, path::Path, sync::Mutex};
+
+use raytracer_v2::{rng, scene, scene_file, Camera, Color};
+
+// The seed is global, so tests that change it take turns
+static SEED: Mutex<()> = Mutex::new(());
+
+// Scene files with random layouts written out by `cargo run --example scene_layouts`
+const WRITTEN_OUT: [&str; 2] = ["random_spheres", "book2_final"];
+
+fn small(camera: Camera) -> Camera {
+    Camera {
+        image_width: 32,
+        samples_per_pixel: 2,
+        ..camera
+    }
+}
+
+// Transforms composed in another order can round the last bit differently
+fn close(a: Color, b: Color) -> bool {
+    let near = |x: f64, y: f64| (x - y).abs() <= 1e-9 * y.abs().max(1.0);
+    near(a.r(), b.r()) && near(a.g(), b.g()) && near(a.b(), b.b())
+}
+
+#[test]
+fn scene_files_match_builtin_scenes() {
+    let _seed = SEED.lock().unwrap();
+    // The written out layouts are the ones drawn with the default seed
+    rng::set_seed(0);
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+    let mut compared = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let Some(builtin) = scene::find_scene(name) else {
+            continue;
+        };
+
+        let loaded = scene_file::load(&path).unwrap();
+        let from_file = small(loaded.camera).render(&loaded.world, &loaded.lights);
+        let expected =
+            small((builtin.camera.camera)()).render(&(builtin.world)(), &(builtin.lights)());
+        assert_eq!(from_file.width(), expected.width(), "{name}");
+        assert_eq!(from_file.height(), expected.height(), "{name}");
+
+        let differing = from_file
+            .pixels()
+            .iter()
+            .zip(expected.pixels())
+            .filter(|(a, b)| !close(**a, **b))
+            .count();
+        let hint = if WRITTEN_OUT.contains(&name) {
+            ", run `cargo run --example scene_layouts` if the random numbers changed"
+        } else {
+            ""
+        };
+        assert_eq!(differing, 0, "{name}: {differing} pixels differ{hint}");
+        compared += 1;
+    }
+    assert!(compared > 0);
+}
+
+#[test]
+fn written_out_layouts_ignore_the_seed() {
+    let _seed = SEED.lock().unwrap();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+    for name in WRITTEN_OUT {
+        let path = dir.join(format!("{name}.toml"));
+        rng::set_seed(1);
+        let reseeded = scene_file::load(&path).unwrap();
+        rng::set_seed(0);
+        let loaded = scene_file::load(&path).unwrap();
+
+        let camera = small(loaded.camera);
+        let image = camera.render(&loaded.world, &loaded.lights);
+        let reseeded_image = camera.render(&reseeded.world, &reseeded.lights);
+        assert!(image.pixels() == reseeded_image.pixels(), "{name}");
+    }
+}